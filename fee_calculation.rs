@@ -0,0 +1,88 @@
+elrond_wasm::imports!();
+elrond_wasm::derive_imports!();
+
+/// Fees are expressed in basis points: 10_000 bps = 100%, 1 bps = 0.01%.
+pub const FEE_DENOMINATOR: u32 = 10_000;
+
+/// How the fee of a payment is rounded to a whole amount of token.
+/// `Down` (default) favors the payer, `Up` the fees address, `HalfEven` rounds to the nearest
+/// and ties to even. `Accumulate` rounds down but keeps the dropped fractions per token,
+/// and adds a whole unit to the fee each time they reach one.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, PartialEq, Eq, Clone, Copy)]
+pub enum FeeRounding {
+    Down,
+    Up,
+    HalfEven,
+    Accumulate,
+}
+
+/// Computes the fee of an amount from a rate in basis points, with the rounding of the token
+/// set by the `setFeeRounding` endpoint of the contract. The net amount is always derived
+/// as the amount minus this fee, so both sum up exactly.
+#[elrond_wasm::module]
+pub trait FeeCalculationModule {
+    /// Returns `amount * fee_basis_points / FEE_DENOMINATOR` rounded as set for `token_id`.
+    /// Never more than `amount` as long as `fee_basis_points` is at most `FEE_DENOMINATOR`.
+    /// In `Accumulate` rounding, also returns the remainder to keep with `keep_fee_remainder`
+    /// once this fee is the one charged.
+    fn apply_fee_rate(
+        &self,
+        token_id: &TokenIdentifier,
+        amount: &BigUint,
+        fee_basis_points: &BigUint,
+    ) -> (BigUint, Option<BigUint>) {
+        let denominator = BigUint::from(FEE_DENOMINATOR);
+        let scaled_fees = amount.clone() * fee_basis_points.clone();
+        let mut fees = scaled_fees.clone() / denominator.clone();
+        let remainder = scaled_fees % denominator.clone();
+        if remainder == 0 {
+            return (fees, None);
+        }
+
+        match self.fee_rounding(token_id).get() {
+            FeeRounding::Down => {},
+            FeeRounding::Up => fees += 1u32,
+            FeeRounding::HalfEven => {
+                let twice_remainder = remainder * 2u32;
+                if twice_remainder > denominator
+                    || (twice_remainder == denominator && fees.clone() % 2u32 == 1u32)
+                {
+                    fees += 1u32;
+                }
+            },
+            FeeRounding::Accumulate => {
+                let accumulated = self.fee_remainder(token_id).get() + remainder;
+                fees += accumulated.clone() / denominator.clone();
+                return (fees, Some(accumulated % denominator));
+            },
+        }
+        (fees, None)
+    }
+
+    fn keep_fee_remainder(&self, token_id: &TokenIdentifier, fee_remainder: Option<BigUint>) {
+        if let Some(fee_remainder) = fee_remainder {
+            self.fee_remainder(token_id).set(&fee_remainder);
+        }
+    }
+
+    // storage
+
+    #[view(getFeeRounding)]
+    #[storage_mapper("feeRounding")]
+    fn fee_rounding(&self, token_id: &TokenIdentifier) -> SingleValueMapper<FeeRounding>;
+
+    /// Fractions of a unit of `token_id` dropped from fees so far in `Accumulate` rounding,
+    /// in `1 / FEE_DENOMINATOR` units.
+    #[view(getFeeRemainder)]
+    #[storage_mapper("feeRemainder")]
+    fn fee_remainder(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    // events
+
+    #[event("feeRoundingChanged")]
+    fn fee_rounding_changed_event(
+        &self,
+        #[indexed] token_id: &TokenIdentifier,
+        #[indexed] fee_rounding: &FeeRounding,
+    );
+}
\ No newline at end of file
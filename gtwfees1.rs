@@ -1,42 +1,326 @@
 #![no_std]
 
+extern crate alloc;
+
 elrond_wasm::imports!();
+elrond_wasm::derive_imports!();
+
+mod fee_calculation;
+
+use fee_calculation::{FeeRounding, FEE_DENOMINATOR};
+
+mod dex_pair_proxy {
+    elrond_wasm::imports!();
+
+    /// The swap endpoint of a DEX pair contract, see `payMerchantConverted`.
+    #[elrond_wasm::proxy]
+    pub trait DexPair {
+        #[payable("*")]
+        #[endpoint(swapTokensFixedInput)]
+        fn swap_tokens_fixed_input(
+            &self,
+            #[payment_token] token_in: TokenIdentifier,
+            #[payment_nonce] token_nonce: u64,
+            #[payment_amount] amount_in: BigUint,
+            token_out: TokenIdentifier,
+            amount_out_min: BigUint,
+        );
+    }
+}
+
+mod egld_wrapper_proxy {
+    elrond_wasm::imports!();
+
+    /// Wraps EGLD into its ESDT counterpart, since DEX pairs only trade ESDTs.
+    #[elrond_wasm::proxy]
+    pub trait EgldWrapper {
+        #[payable("EGLD")]
+        #[endpoint(wrapEgld)]
+        fn wrap_egld(&self, #[payment_amount] amount: BigUint);
+    }
+}
+
+const BASIS_POINTS_PER_PERCENT: u32 = 100;
+
+/// Version of the storage layout written by this code, see `migrate_storage`:
+/// - 0: whole-percent fee under `feesInPercent`, single `acceptedPaymentTokenId`;
+/// - 1: fee in basis points under `feeBasisPoints`, single `acceptedPaymentTokenId`;
+/// - 2: per-token configuration under `acceptedTokens`.
+pub const SCHEMA_VERSION: u32 = 2;
+/// Merchant id of payments made to the gateway itself: the net amount goes
+/// to the rest address, or to the payout split when there is one.
+const GATEWAY_MERCHANT_ID: u64 = 0;
+
+/// Length, in seconds, of the rolling window of the daily volume limit.
+const DAILY_VOLUME_WINDOW: u64 = 24 * 60 * 60;
+
+/// One beneficiary of the net amount of a payment, see `setPayoutSplit`.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi)]
+pub struct PayoutShare<M: ManagedTypeApi> {
+    pub address: ManagedAddress<M>,
+    pub weight: u32,
+}
+
+/// How the fee and net amounts of a payment reach their recipients.
+/// `Push` sends them within the payment, `Pull` credits internal balances withdrawn with `claim`.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, PartialEq, Eq, Clone, Copy)]
+pub enum PayoutMode {
+    Push,
+    Pull,
+}
+
+/// Amount sent, or credited in `Pull` payout mode, to one recipient of a payment.
+#[derive(TopEncode, NestedEncode, TypeAbi)]
+pub struct Payout<M: ManagedTypeApi> {
+    pub address: ManagedAddress<M>,
+    pub amount: BigUint<M>,
+}
+
+/// Data of the `payment` event, emitted once per processed payment.
+/// For single NFTs the fee is paid in `fee_token_id`, otherwise it is the payment token.
+/// `recipients` amounts are in `payout_token_id`, which differs from the payment token
+/// when the payment was converted to the settlement token of the merchant.
+#[derive(TopEncode, TypeAbi)]
+pub struct PaymentEvent<M: ManagedTypeApi> {
+    pub payment_id: u64,
+    pub merchant_id: u64,
+    pub reference: ManagedBuffer<M>,
+    pub gross_amount: BigUint<M>,
+    pub fee_token_id: TokenIdentifier<M>,
+    pub fee_amount: BigUint<M>,
+    pub net_amount: BigUint<M>,
+    pub fees_addr: ManagedAddress<M>,
+    pub payout_token_id: TokenIdentifier<M>,
+    pub recipients: Vec<Payout<M>>,
+}
+
+/// A processed payment, stored under its id. `reference` is empty when none was given.
+/// `payout_amount` of `payout_token_id` is what the payee received, in the settlement token
+/// when the payment was converted.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi)]
+pub struct PaymentRecord<M: ManagedTypeApi> {
+    pub payer: ManagedAddress<M>,
+    pub merchant_id: u64,
+    pub token_id: TokenIdentifier<M>,
+    pub nonce: u64,
+    pub gross_amount: BigUint<M>,
+    pub fee_token_id: TokenIdentifier<M>,
+    pub fee_amount: BigUint<M>,
+    pub payout_token_id: TokenIdentifier<M>,
+    pub payout_amount: BigUint<M>,
+    pub reference: ManagedBuffer<M>,
+    pub timestamp: u64,
+    pub refunded_amount: BigUint<M>,
+}
+
+/// Status of an invoice. `Expired` is never stored, it is derived from the expiry
+/// of an `Open` invoice, see `getInvoiceStatus`.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, PartialEq, Eq, Clone, Copy)]
+pub enum InvoiceStatus {
+    Open,
+    Paid,
+    Expired,
+    Cancelled,
+}
+
+/// An exact amount to be paid with `payInvoice` before `expiry` (block timestamp).
+/// `nonce` is 0 for fungible tokens.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi)]
+pub struct Invoice<M: ManagedTypeApi> {
+    pub creator: ManagedAddress<M>,
+    pub merchant_id: u64,
+    pub token_id: TokenIdentifier<M>,
+    pub nonce: u64,
+    pub amount: BigUint<M>,
+    pub expiry: u64,
+    pub memo: ManagedBuffer<M>,
+    pub status: InvoiceStatus,
+    pub payment_id: u64,
+}
+
+/// A merchant registered by the owner, paid with `payMerchant`.
+/// `fee_basis_points` replaces the fee of the token when set.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi)]
+pub struct Merchant<M: ManagedTypeApi> {
+    pub payout_address: ManagedAddress<M>,
+    pub fee_basis_points: Option<BigUint<M>>,
+    pub active: bool,
+}
+
+/// Cumulative figures of the payments in one token, see `getTokenStats` and `getMerchantStats`.
+/// The net amount of a converted payment is paid out in the settlement token, it adds
+/// to the figures of that token only.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi)]
+pub struct PaymentStats<M: ManagedTypeApi> {
+    pub payment_count: u64,
+    pub gross_volume: BigUint<M>,
+    pub fees_collected: BigUint<M>,
+    pub net_paid_out: BigUint<M>,
+}
+
+/// How much of a payment its payee can refund. The fees address always keeps the fee:
+/// `Retained` (default) caps refunds to the net amount, `CoveredByPayee` allows up to
+/// the gross amount, the payee paying the fee part back out of its own funds.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, PartialEq, Eq, Clone, Copy)]
+pub enum RefundFeePolicy {
+    Retained,
+    CoveredByPayee,
+}
+
+/// State of an escrowed payment.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi, PartialEq, Eq, Clone, Copy)]
+pub enum EscrowStatus {
+    Held,
+    Released,
+    Cancelled,
+}
+
+/// A payment held by the contract, see `createEscrow`.
+/// Anyone can release it once `deadline` (block timestamp) is reached.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi)]
+pub struct Escrow<M: ManagedTypeApi> {
+    pub payer: ManagedAddress<M>,
+    pub merchant_id: u64,
+    pub token_id: TokenIdentifier<M>,
+    pub nonce: u64,
+    pub amount: BigUint<M>,
+    pub reference: ManagedBuffer<M>,
+    pub deadline: u64,
+    pub status: EscrowStatus,
+    pub payment_id: u64,
+}
+
+/// A payment to check and dispatch, see `process_payment`.
+pub struct PaymentRequest<M: ManagedTypeApi> {
+    pub payer: ManagedAddress<M>,
+    pub merchant_id: u64,
+    pub token_id: TokenIdentifier<M>,
+    pub nonce: u64,
+    pub amount: BigUint<M>,
+    pub reference: ManagedBuffer<M>,
+    pub referrer: Option<ManagedAddress<M>>,
+    /// When set, the net amount is swapped to the settlement token of the merchant
+    /// and the swap must give at least this amount.
+    pub min_amount_out: Option<BigUint<M>>,
+    /// Set for escrowed payments, whose payment limits were checked when the funds were held.
+    pub allowance_consumed: bool,
+}
+
+/// A transfer decided while processing payments, sent or credited by `execute_payouts`.
+pub struct PendingPayout<M: ManagedTypeApi> {
+    pub to: ManagedAddress<M>,
+    pub token_id: TokenIdentifier<M>,
+    pub nonce: u64,
+    pub amount: BigUint<M>,
+    pub data: ManagedBuffer<M>,
+}
+
+/// Amount paid by an address at `timestamp`, counted in its daily volume, see `setPaymentLimits`.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi)]
+pub struct VolumeEntry<M: ManagedTypeApi> {
+    pub timestamp: u64,
+    pub amount: BigUint<M>,
+}
+
+/// `threshold, fee_basis_points` pairs of `setFeeTiers`.
+type FeeTierArgs<M> = MultiArgVec<MultiArg2<BigUint<M>, BigUint<M>>>;
+
+/// Fee applied to payments strictly below `threshold`, see `setFeeTiers`.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi)]
+pub struct FeeTier<M: ManagedTypeApi> {
+    pub threshold: BigUint<M>,
+    pub fee_basis_points: BigUint<M>,
+}
+
+/// A change of the gateway configuration voted by the board, see `propose`.
+/// Each action takes the arguments of the owner endpoint of the same name,
+/// which performs it directly while there is no board.
+/// The board itself is managed with the last three actions.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi)]
+pub enum BoardAction<M: ManagedTypeApi> {
+    AddAcceptedToken(TokenIdentifier<M>, BigUint<M>, BigUint<M>),
+    RemoveAcceptedToken(TokenIdentifier<M>),
+    SetNftFlatFee(TokenIdentifier<M>, TokenIdentifier<M>, BigUint<M>),
+    RemoveNftCollection(TokenIdentifier<M>),
+    SetMinAmount(TokenIdentifier<M>, BigUint<M>),
+    SetFeeBasisPoints(TokenIdentifier<M>, BigUint<M>),
+    SetFeeRounding(TokenIdentifier<M>, FeeRounding),
+    SetFeeTiers(TokenIdentifier<M>, Vec<FeeTier<M>>),
+    SetFeeBounds(TokenIdentifier<M>, BigUint<M>, BigUint<M>),
+    RegisterReferrer(ManagedAddress<M>),
+    UnregisterReferrer(ManagedAddress<M>),
+    SetReferralFeeShare(BigUint<M>),
+    SetFeeExemption(ManagedAddress<M>, BigUint<M>),
+    RemoveFeeExemption(ManagedAddress<M>),
+    SetFeesAddr(ManagedAddress<M>),
+    SetRestAddr(ManagedAddress<M>),
+    SetPayoutSplit(ManagedAddress<M>, Vec<PayoutShare<M>>),
+    ClearPayoutSplit,
+    RegisterMerchant(ManagedAddress<M>, Option<BigUint<M>>),
+    SetMerchantPayoutAddress(u64, ManagedAddress<M>),
+    SetMerchantFeeBasisPoints(u64, Option<BigUint<M>>),
+    SetMerchantSettlementToken(u64, Option<TokenIdentifier<M>>),
+    SetDexPair(TokenIdentifier<M>, TokenIdentifier<M>, ManagedAddress<M>),
+    RemoveDexPair(TokenIdentifier<M>, TokenIdentifier<M>),
+    SetEgldWrapper(ManagedAddress<M>, TokenIdentifier<M>),
+    SetConvertFees(bool),
+    AddBoardMember(ManagedAddress<M>),
+    RemoveBoardMember(ManagedAddress<M>),
+    ChangeQuorum(usize),
+}
+
+/// An action waiting for the signatures of the board.
+#[derive(TopEncode, TopDecode, NestedEncode, NestedDecode, TypeAbi)]
+pub struct Proposal<M: ManagedTypeApi> {
+    pub proposer: ManagedAddress<M>,
+    pub action: BoardAction<M>,
+}
 
-/// A contract that allows anyone to send a fixed sum, and dispatch to address.
-/// Sending funds to the contract is called "ping".
-/// Taking the same funds back is called "pong".
+/// A payment gateway: anyone sends accepted tokens, the contract takes a fee
+/// for the fees address and dispatches the rest to the rest address or a merchant.
 ///
-/// Restrictions:
-/// - Only the set amount can be `ping`-ed, no more, no less.
-/// - `pong` can only be called after a certain period after `ping`.
+/// Payments are forwarded right away, or held in escrow with `createEscrow`
+/// until they are released or cancelled.
+///
+/// Once a board is set up with `setupBoard`, the deployment configuration
+/// (accepted tokens, minimum amounts, fees, fees and rest addresses) can only change
+/// through board proposals reaching the quorum.
 #[elrond_wasm::contract]
-pub trait GtwFees1 {
+pub trait GtwFees1: fee_calculation::FeeCalculationModule {
     /// Necessary configuration when deploying:
     /// `min_amount` - The minimum value of token to be handle
-    /// `fees_in_percent` - The value of fees to get from an amount in percent (e.g.: 12 for 12% of an amount in fees)
+    /// `fee_basis_points` - The value of fees to get from an amount in basis points (e.g.: 75 for 0.75% of an amount in fees)
     /// `fees_addr` - ERD1 Address to receive fees
     /// `rest_addr` - ERD1 Addr to receive rest of payment
     /// `token_id` - Optional. The Token Identifier of the token that is going to be used. Default is "EGLD".
+    ///
+    /// `min_amount` and `fee_basis_points` apply to `token_id`, more tokens can be added with `addAcceptedToken`.
+    ///
+    /// Upgrading the contract runs `init` again: storage written by a previous version
+    /// is migrated to `SCHEMA_VERSION` and the arguments are ignored, the configuration
+    /// only changes through its setters. Mappers added by a new version need no migration
+    /// step, they start empty.
     #[init]
     fn init(
         &self,
         min_amount: BigUint,
-        fees_in_percent: BigUint,
+        fee_basis_points: BigUint,
         fees_addr: ManagedAddress,
         rest_addr: ManagedAddress,
         #[var_args] opt_token_id: OptionalArg<TokenIdentifier>,
     ) -> SCResult<()> {
-        require!(min_amount >= 0, "Min amount must be greater than or equal to zero");
-        self.min_amount().set(&min_amount);
-        require!(fees_in_percent > 0, "Fees in percent must be greater than zero");
-        self.fees_in_percent().set(&fees_in_percent);
         let token_id = match opt_token_id {
             OptionalArg::Some(t) => t,
             OptionalArg::None => TokenIdentifier::egld(),
         };
+        if self.is_deployed() {
+            return self.migrate_storage();
+        }
+
+        self.schema_version().set(&SCHEMA_VERSION);
+        self.add_token_config(&token_id, &min_amount, &fee_basis_points)?;
         self.accepted_fees_addr_id().set(&fees_addr);
         self.accepted_rest_addr_id().set(&rest_addr);
-        self.accepted_payment_token_id().set(&token_id);
 
         Ok(())
     }
@@ -44,56 +328,3328 @@ pub trait GtwFees1 {
     // endpoints
 
     /// User sends some tokens 
-    /// Optional `_data` argument is ignored.
+    /// Optional `reference` (e.g. an order id) is stored with the payment,
+    /// added to the transfer data and to the `payment` event.
+    /// Fungible tokens, SFT quantities and Meta-ESDT amounts are split the same way,
+    /// the nonce of the payment is kept when forwarding. Single NFTs go through `sendNft`.
     #[payable("*")]
-    #[endpoint]
-    fn sendToken(
+    #[endpoint(sendToken)]
+    fn send_token(
+        &self,
+        #[payment_token] payment_token: TokenIdentifier,
+        #[payment_nonce] payment_nonce: u64,
+        #[payment_amount] payment_amount: BigUint,
+        #[var_args] opt_reference: OptionalArg<ManagedBuffer>,
+    ) -> SCResult<()> {
+        self.process_direct_payment(
+            GATEWAY_MERCHANT_ID,
+            payment_token,
+            payment_nonce,
+            payment_amount,
+            opt_reference,
+            None,
+        )
+    }
+
+    /// Same as `sendToken`, `referrer` must be a registered referrer, it earns a share of the fee.
+    #[payable("*")]
+    #[endpoint(sendTokenReferred)]
+    fn send_token_referred(
+        &self,
+        #[payment_token] payment_token: TokenIdentifier,
+        #[payment_nonce] payment_nonce: u64,
+        #[payment_amount] payment_amount: BigUint,
+        referrer: ManagedAddress,
+        #[var_args] opt_reference: OptionalArg<ManagedBuffer>,
+    ) -> SCResult<()> {
+        self.process_direct_payment(
+            GATEWAY_MERCHANT_ID,
+            payment_token,
+            payment_nonce,
+            payment_amount,
+            opt_reference,
+            Some(referrer),
+        )
+    }
+
+    /// Same as `sendToken`, but the net amount goes to the payout address of merchant `merchant_id`
+    /// and the fee override of the merchant applies.
+    #[payable("*")]
+    #[endpoint(payMerchant)]
+    fn pay_merchant(
+        &self,
+        #[payment_token] payment_token: TokenIdentifier,
+        #[payment_nonce] payment_nonce: u64,
+        #[payment_amount] payment_amount: BigUint,
+        merchant_id: u64,
+        #[var_args] opt_reference: OptionalArg<ManagedBuffer>,
+    ) -> SCResult<()> {
+        require!(merchant_id != GATEWAY_MERCHANT_ID, "Invalid merchant id");
+        self.process_direct_payment(
+            merchant_id,
+            payment_token,
+            payment_nonce,
+            payment_amount,
+            opt_reference,
+            None,
+        )
+    }
+
+    /// Same as `payMerchant`, `referrer` must be a registered referrer, it earns a share of the fee.
+    #[payable("*")]
+    #[endpoint(payMerchantReferred)]
+    fn pay_merchant_referred(
+        &self,
+        #[payment_token] payment_token: TokenIdentifier,
+        #[payment_nonce] payment_nonce: u64,
+        #[payment_amount] payment_amount: BigUint,
+        merchant_id: u64,
+        referrer: ManagedAddress,
+        #[var_args] opt_reference: OptionalArg<ManagedBuffer>,
+    ) -> SCResult<()> {
+        require!(merchant_id != GATEWAY_MERCHANT_ID, "Invalid merchant id");
+        self.process_direct_payment(
+            merchant_id,
+            payment_token,
+            payment_nonce,
+            payment_amount,
+            opt_reference,
+            Some(referrer),
+        )
+    }
+
+    /// Same as `payMerchant`, but the net amount is swapped through a DEX pair to the settlement
+    /// token of the merchant, which receives at least `min_amount_out` of it.
+    /// EGLD is wrapped before the swap. When fee conversion is enabled the fee is swapped too,
+    /// and `min_amount_out` then applies to the fee and net amount together.
+    #[payable("*")]
+    #[endpoint(payMerchantConverted)]
+    fn pay_merchant_converted(
+        &self,
+        #[payment_token] payment_token: TokenIdentifier,
+        #[payment_nonce] payment_nonce: u64,
+        #[payment_amount] payment_amount: BigUint,
+        merchant_id: u64,
+        min_amount_out: BigUint,
+        #[var_args] opt_reference: OptionalArg<ManagedBuffer>,
+    ) -> SCResult<()> {
+        self.require_not_paused()?;
+        require!(merchant_id != GATEWAY_MERCHANT_ID, "Invalid merchant id");
+        require!(payment_nonce == 0, "Only fungible payments can be converted");
+        require!(
+            !self.merchant_settlement_token(merchant_id).is_empty(),
+            "Merchant has no settlement token"
+        );
+        require!(min_amount_out > 0, "Min amount out must be greater than zero");
+        self.process_single_payment(&PaymentRequest {
+            payer: self.blockchain().get_caller(),
+            merchant_id,
+            token_id: payment_token,
+            nonce: payment_nonce,
+            amount: payment_amount,
+            reference: opt_reference.into_option().unwrap_or_else(ManagedBuffer::new),
+            referrer: None,
+            min_amount_out: Some(min_amount_out),
+            allowance_consumed: false,
+        })?;
+
+        Ok(())
+    }
+
+    /// Same as `sendToken` for several tokens sent in one multi-ESDT transfer.
+    /// Each token is checked against its own configuration and split on its own,
+    /// then everything owed to the same recipient is sent in one multi-transfer.
+    /// Every token is recorded as a separate payment with the same optional `reference`.
+    #[payable("*")]
+    #[endpoint(sendMultiToken)]
+    fn send_multi_token(
+        &self,
+        #[var_args] opt_reference: OptionalArg<ManagedBuffer>,
+    ) -> SCResult<()> {
+        self.require_not_paused()?;
+        let payments = self.call_value().all_esdt_transfers();
+        require!(!payments.is_empty(), "No payment");
+        let reference = opt_reference.into_option().unwrap_or_else(ManagedBuffer::new);
+        require!(
+            payments.len() == 1 || reference.is_empty() || !self.unique_references().get(),
+            "A unique reference can only be used by one payment"
+        );
+
+        let payer = self.blockchain().get_caller();
+        let mut payouts = Vec::new();
+        for payment in payments.iter() {
+            require!(
+                payment.token_type != EsdtTokenType::NonFungible,
+                "NFT payments must use sendNft"
+            );
+            self.process_payment(
+                &PaymentRequest {
+                    payer: payer.clone(),
+                    merchant_id: GATEWAY_MERCHANT_ID,
+                    token_id: payment.token_identifier,
+                    nonce: payment.token_nonce,
+                    amount: payment.amount,
+                    reference: reference.clone(),
+                    referrer: None,
+                    min_amount_out: None,
+                    allowance_consumed: false,
+                },
+                &mut payouts,
+            )?;
+        }
+        self.execute_payouts(payouts, &reference);
+
+        Ok(())
+    }
+
+    /// Holds the payment in the contract instead of forwarding it.
+    /// `merchant_id` is the merchant to pay on release, `0` for the gateway itself.
+    /// The payer can release it at any time, anyone can after the escrow timeout,
+    /// the payee or the owner can cancel it to refund the payer, and the payer can reclaim it
+    /// once it can no longer be released. The fee is taken on release. Returns the escrow id.
+    /// With unique references, `reference` is reserved until the escrow is released or refunded.
+    /// The payment limits of the payer apply here, not on release.
+    #[payable("*")]
+    #[endpoint(createEscrow)]
+    fn create_escrow(
+        &self,
+        #[payment_token] payment_token: TokenIdentifier,
+        #[payment_nonce] payment_nonce: u64,
+        #[payment_amount] payment_amount: BigUint,
+        merchant_id: u64,
+        #[var_args] opt_reference: OptionalArg<ManagedBuffer>,
+    ) -> SCResult<u64> {
+        self.require_not_paused()?;
+        require!(self.escrow_timeout().get() > 0, "Escrow is not enabled");
+        self.require_allowed_payer(&self.blockchain().get_caller())?;
+        require!(
+            self.call_value().esdt_token_type() != EsdtTokenType::NonFungible,
+            "NFT payments must use sendNft"
+        );
+        let reference = opt_reference.into_option().unwrap_or_else(ManagedBuffer::new);
+        self.require_valid_payment(merchant_id, &payment_token, &payment_amount, &reference)?;
+        let payer = self.blockchain().get_caller();
+        self.consume_payment_allowance(&payer, &payment_token, &payment_amount)?;
+
+        let escrow_id = self.last_escrow_id().get() + 1;
+        self.last_escrow_id().set(&escrow_id);
+        if !reference.is_empty() && self.unique_references().get() {
+            self.escrow_id_by_reference(&reference).set(&escrow_id);
+        }
+        self.escrows(escrow_id).set(&Escrow {
+            payer: payer.clone(),
+            merchant_id,
+            token_id: payment_token,
+            nonce: payment_nonce,
+            amount: payment_amount,
+            reference,
+            deadline: self.blockchain().get_block_timestamp() + self.escrow_timeout().get(),
+            status: EscrowStatus::Held,
+            payment_id: 0,
+        });
+        self.escrow_created_event(&payer, escrow_id);
+
+        Ok(escrow_id)
+    }
+
+    /// Processes a held payment as in `sendToken` or `payMerchant`.
+    /// Payer only, or anyone once the deadline is reached. Not while the gateway is paused.
+    #[endpoint(releaseEscrow)]
+    fn release_escrow(&self, escrow_id: u64) -> SCResult<()> {
+        self.require_not_paused()?;
+        require!(!self.escrows(escrow_id).is_empty(), "Escrow does not exist");
+        let mut escrow = self.escrows(escrow_id).get();
+        require!(escrow.status == EscrowStatus::Held, "Escrow is not held");
+        let caller = self.blockchain().get_caller();
+        require!(
+            caller == escrow.payer || self.blockchain().get_block_timestamp() >= escrow.deadline,
+            "Only the payer can release the escrow before its deadline"
+        );
+
+        self.free_escrow_reference(escrow_id, &escrow.reference);
+        let payment_id = self.process_single_payment(&PaymentRequest {
+            payer: escrow.payer.clone(),
+            merchant_id: escrow.merchant_id,
+            token_id: escrow.token_id.clone(),
+            nonce: escrow.nonce,
+            amount: escrow.amount.clone(),
+            reference: escrow.reference.clone(),
+            referrer: None,
+            min_amount_out: None,
+            allowance_consumed: true,
+        })?;
+
+        escrow.status = EscrowStatus::Released;
+        escrow.payment_id = payment_id;
+        self.escrows(escrow_id).set(&escrow);
+        self.escrow_released_event(&caller, escrow_id, payment_id);
+
+        Ok(())
+    }
+
+    /// Refunds a held payment to the payer, no fee is taken. Payee or owner only.
+    #[endpoint(cancelEscrow)]
+    fn cancel_escrow(&self, escrow_id: u64) -> SCResult<()> {
+        require!(!self.escrows(escrow_id).is_empty(), "Escrow does not exist");
+        let mut escrow = self.escrows(escrow_id).get();
+        require!(escrow.status == EscrowStatus::Held, "Escrow is not held");
+        let caller = self.blockchain().get_caller();
+        require!(
+            caller == self.payee_addr(escrow.merchant_id)
+                || caller == self.blockchain().get_owner_address(),
+            "Only the payee or the owner can cancel the escrow"
+        );
+
+        self.refund_escrow(escrow_id, &mut escrow);
+        self.escrow_cancelled_event(&caller, escrow_id);
+
+        Ok(())
+    }
+
+    /// Refunds a held payment to its payer when it can no longer be released,
+    /// e.g. its token is no longer accepted or its merchant is inactive. Payer only.
+    #[endpoint(reclaimEscrow)]
+    fn reclaim_escrow(&self, escrow_id: u64) -> SCResult<()> {
+        require!(!self.escrows(escrow_id).is_empty(), "Escrow does not exist");
+        let mut escrow = self.escrows(escrow_id).get();
+        require!(escrow.status == EscrowStatus::Held, "Escrow is not held");
+        let caller = self.blockchain().get_caller();
+        require!(caller == escrow.payer, "Only the payer can reclaim the escrow");
+        require!(
+            !self.is_releasable_escrow(&escrow),
+            "Escrow can still be released"
+        );
+
+        self.refund_escrow(escrow_id, &mut escrow);
+        self.escrow_cancelled_event(&caller, escrow_id);
+
+        Ok(())
+    }
+
+    /// Refunds the payer of `payment_id` with the tokens sent along, fully or partially.
+    /// Only the payee of the payment can refund, within the refund window.
+    /// The total refunded is capped to the net amount, or to the gross amount
+    /// when the refund fee policy is `CoveredByPayee`. The fee is never taken back from the fees address.
+    /// Converted payments are refunded in the settlement token, up to the amount the payee received.
+    #[payable("*")]
+    #[endpoint(refundPayment)]
+    fn refund_payment(
+        &self,
+        #[payment_token] payment_token: TokenIdentifier,
+        #[payment_nonce] payment_nonce: u64,
+        #[payment_amount] payment_amount: BigUint,
+        payment_id: u64,
+    ) -> SCResult<()> {
+        require!(self.refund_window().get() > 0, "Refunds are not enabled");
+        require!(!self.payments(payment_id).is_empty(), "Payment does not exist");
+        let mut payment = self.payments(payment_id).get();
+        let caller = self.blockchain().get_caller();
+        require!(
+            caller == self.payee_addr(payment.merchant_id),
+            "Only the payee can refund the payment"
+        );
+        require!(
+            self.blockchain().get_block_timestamp() <= payment.timestamp + self.refund_window().get(),
+            "Refund window has passed"
+        );
+        let converted = payment.payout_token_id != payment.token_id;
+        let payout_nonce = if converted { 0 } else { payment.nonce };
+        require!(
+            payment_token == payment.payout_token_id && payment_nonce == payout_nonce,
+            "Refund must be in the payout token"
+        );
+        require!(payment_amount > 0, "Refund amount must be greater than zero");
+
+        let mut refundable = payment.gross_amount.clone();
+        if converted {
+            refundable = payment.payout_amount.clone();
+        } else if self.refund_fee_policy().get() == RefundFeePolicy::Retained
+            && payment.fee_token_id == payment.token_id
+        {
+            refundable -= &payment.fee_amount;
+        }
+        payment.refunded_amount += &payment_amount;
+        require!(
+            payment.refunded_amount <= refundable,
+            "Refund exceeds the refundable amount"
+        );
+        self.payments(payment_id).set(&payment);
+
+        self.pay_out(
+            &payment.payer,
+            &payment_token,
+            payment_nonce,
+            &payment_amount,
+            &self.transfer_data(b"refund from gtw sc", &payment.reference),
+        );
+        self.refund_event(
+            &payment.payer,
+            payment_id,
+            &payment_amount,
+            &payment.refunded_amount,
+        );
+
+        Ok(())
+    }
+
+    /// Creates an invoice of exactly `amount` of `token_id` with `token_nonce` (0 for fungible tokens),
+    /// payable until `expiry` (block timestamp).
+    /// Optional `merchant_id` makes it an invoice of that merchant, which only its payout address
+    /// or the owner can create. Otherwise only the rest address or the owner can create it.
+    /// Returns the invoice id.
+    #[endpoint(createInvoice)]
+    fn create_invoice(
+        &self,
+        token_id: TokenIdentifier,
+        token_nonce: u64,
+        amount: BigUint,
+        expiry: u64,
+        memo: ManagedBuffer,
+        #[var_args] opt_merchant_id: OptionalArg<u64>,
+    ) -> SCResult<u64> {
+        let caller = self.blockchain().get_caller();
+        let merchant_id = opt_merchant_id.into_option().unwrap_or(GATEWAY_MERCHANT_ID);
+        if merchant_id != GATEWAY_MERCHANT_ID {
+            self.require_active_merchant(merchant_id)?;
+        }
+        require!(
+            caller == self.payee_addr(merchant_id) || caller == self.blockchain().get_owner_address(),
+            "Only the payee or the owner can create invoices"
+        );
+        self.require_accepted_token(&token_id)?;
+        require!(
+            amount > self.min_amount(&token_id).get(),
+            "The invoice amount must be greater than the min_amount"
+        );
+        require!(
+            expiry > self.blockchain().get_block_timestamp(),
+            "Expiry must be in the future"
+        );
+
+        let invoice_id = self.last_invoice_id().get() + 1;
+        self.last_invoice_id().set(&invoice_id);
+        self.invoices(invoice_id).set(&Invoice {
+            creator: caller.clone(),
+            merchant_id,
+            token_id,
+            nonce: token_nonce,
+            amount,
+            expiry,
+            memo,
+            status: InvoiceStatus::Open,
+            payment_id: 0,
+        });
+        self.invoice_created_event(&caller, invoice_id);
+
+        Ok(invoice_id)
+    }
+
+    /// Pays an open invoice, the payment must be exactly its token, nonce and amount.
+    /// The payment is then handled as in `sendToken`.
+    #[payable("*")]
+    #[endpoint(payInvoice)]
+    fn pay_invoice(
         &self,
         #[payment_token] payment_token: TokenIdentifier,
+        #[payment_nonce] payment_nonce: u64,
         #[payment_amount] payment_amount: BigUint,
+        invoice_id: u64,
     ) -> SCResult<()> {
+        self.require_not_paused()?;
+        require!(
+            self.call_value().esdt_token_type() != EsdtTokenType::NonFungible,
+            "NFT payments must use sendNft"
+        );
+        require!(!self.invoices(invoice_id).is_empty(), "Invoice does not exist");
+        let mut invoice = self.invoices(invoice_id).get();
+        require!(
+            self.invoice_status(&invoice) == InvoiceStatus::Open,
+            "Invoice is not open"
+        );
         require!(
-            payment_token == self.accepted_payment_token_id().get(),
+            payment_token == invoice.token_id && payment_nonce == invoice.nonce,
             "Invalid payment token"
         );
+        require!(payment_amount == invoice.amount, "Payment must be exactly the invoice amount");
+
+        let payer = self.blockchain().get_caller();
+        let payment_id = self.process_single_payment(&PaymentRequest {
+            payer: payer.clone(),
+            merchant_id: invoice.merchant_id,
+            token_id: payment_token,
+            nonce: payment_nonce,
+            amount: payment_amount,
+            reference: ManagedBuffer::new(),
+            referrer: None,
+            min_amount_out: None,
+            allowance_consumed: false,
+        })?;
+
+        invoice.status = InvoiceStatus::Paid;
+        invoice.payment_id = payment_id;
+        self.invoices(invoice_id).set(&invoice);
+        self.invoice_paid_event(&payer, invoice_id, payment_id);
+
+        Ok(())
+    }
+
+    /// Cancels an open invoice. Creator or owner only.
+    #[endpoint(cancelInvoice)]
+    fn cancel_invoice(&self, invoice_id: u64) -> SCResult<()> {
+        require!(!self.invoices(invoice_id).is_empty(), "Invoice does not exist");
+        let mut invoice = self.invoices(invoice_id).get();
+        let caller = self.blockchain().get_caller();
         require!(
-            payment_amount > self.min_amount().get(),
-            "The payment must be greater than the min_amount"
+            caller == invoice.creator || caller == self.blockchain().get_owner_address(),
+            "Only the creator or the owner can cancel the invoice"
+        );
+        require!(
+            invoice.status == InvoiceStatus::Open,
+            "Only open invoices can be cancelled"
+        );
+
+        invoice.status = InvoiceStatus::Cancelled;
+        self.invoices(invoice_id).set(&invoice);
+        self.invoice_cancelled_event(&caller, invoice_id);
+
+        Ok(())
+    }
+
+    /// User sends a single NFT together with the flat fee of its collection,
+    /// as a multi-ESDT transfer: first the NFT, then the fee payment.
+    /// The NFT is forwarded to the main payout address and the fee to the fees address.
+    /// Optional `reference` is handled as in `sendToken`.
+    #[payable("*")]
+    #[endpoint(sendNft)]
+    fn send_nft(&self, #[var_args] opt_reference: OptionalArg<ManagedBuffer>) -> SCResult<()> {
+        self.require_not_paused()?;
+        let reference = opt_reference.into_option().unwrap_or_else(ManagedBuffer::new);
+        self.require_valid_reference(&reference)?;
+        let payments = self.call_value().all_esdt_transfers();
+        require!(
+            payments.len() == 2,
+            "Expected the NFT followed by the fee payment"
+        );
+        let mut payments_iter = payments.iter();
+        let (nft_payment, fee_payment) = match (payments_iter.next(), payments_iter.next()) {
+            (Some(nft_payment), Some(fee_payment)) => (nft_payment, fee_payment),
+            _ => return sc_error!("Expected the NFT followed by the fee payment"),
+        };
+
+        require!(
+            nft_payment.token_type == EsdtTokenType::NonFungible && nft_payment.amount == 1u32,
+            "First payment must be a single NFT"
+        );
+        let collection = nft_payment.token_identifier;
+        require!(
+            !self.nft_fee_token(&collection).is_empty(),
+            "NFT collection is not accepted"
+        );
+        require!(
+            fee_payment.token_identifier == self.nft_fee_token(&collection).get(),
+            "Invalid fee token"
+        );
+        require!(
+            fee_payment.amount == self.nft_fee_amount(&collection).get(),
+            "Fee payment must be exactly the flat fee"
+        );
+
+        let payer = self.blockchain().get_caller();
+        self.require_allowed_payer(&payer)?;
+        let fees_addr = self.accepted_fees_addr_id().get();
+        let payout_addr = self.main_payout_addr();
+        self.pay_out(
+            &fees_addr,
+            &fee_payment.token_identifier,
+            fee_payment.token_nonce,
+            &fee_payment.amount,
+            &self.transfer_data(b"fees from gtw sc", &reference),
+        );
+        self.pay_out(
+            &payout_addr,
+            &collection,
+            nft_payment.token_nonce,
+            &nft_payment.amount,
+            &self.transfer_data(b"payment from gtw sc", &reference),
         );
 
-        let amount_fees = payment_amount.clone() * self.fees_in_percent().get() / BigUint::from(100u32);
-        // let amount_fees = payment_amount.clone() / BigUint::from(10u32);
-        let amount_rest = payment_amount.clone() - amount_fees.clone();
+        let payment_id = self.record_payment(&PaymentRecord {
+            payer: payer.clone(),
+            merchant_id: GATEWAY_MERCHANT_ID,
+            token_id: collection.clone(),
+            nonce: nft_payment.token_nonce,
+            gross_amount: nft_payment.amount.clone(),
+            fee_token_id: fee_payment.token_identifier.clone(),
+            fee_amount: fee_payment.amount.clone(),
+            payout_token_id: collection.clone(),
+            payout_amount: nft_payment.amount.clone(),
+            reference: reference.clone(),
+            timestamp: self.blockchain().get_block_timestamp(),
+            refunded_amount: BigUint::zero(),
+        });
+
+        self.update_stats(
+            GATEWAY_MERCHANT_ID,
+            &collection,
+            &nft_payment.amount,
+            &BigUint::zero(),
+            &nft_payment.amount,
+        );
+        // the flat fee is in another token, it only adds to the fees collected in that token
+        let mut fee_token_stats = self.stats_or_zero(&self.token_stats(&fee_payment.token_identifier));
+        fee_token_stats.fees_collected += &fee_payment.amount;
+        self.token_stats(&fee_payment.token_identifier).set(&fee_token_stats);
 
-        self.send()
-            .direct(&self.accepted_fees_addr_id().get(), &payment_token, 0, &amount_fees, b"fees from gtw sc");
-        self.send()
-            .direct(&self.accepted_rest_addr_id().get(), &payment_token, 0, &amount_rest, b"payment from gtw sc");
+        let recipients = alloc::vec![Payout {
+            address: payout_addr,
+            amount: nft_payment.amount.clone(),
+        }];
+        self.payment_event(
+            &payer,
+            &collection,
+            nft_payment.token_nonce,
+            &PaymentEvent {
+                payment_id,
+                merchant_id: GATEWAY_MERCHANT_ID,
+                reference,
+                gross_amount: nft_payment.amount.clone(),
+                fee_token_id: fee_payment.token_identifier,
+                fee_amount: fee_payment.amount,
+                net_amount: nft_payment.amount,
+                fees_addr,
+                payout_token_id: collection.clone(),
+                recipients,
+            },
+        );
 
         Ok(())
     }
 
-    // storage
+    /// Sends the caller everything credited to them in `Pull` payout mode,
+    /// and their referral commissions.
+    #[endpoint]
+    fn claim(&self) -> SCResult<()> {
+        let caller = self.blockchain().get_caller();
+        let pending_tokens: Vec<(TokenIdentifier, u64)> =
+            self.pending_tokens(&caller).iter().collect();
+        require!(!pending_tokens.is_empty(), "Nothing to claim");
 
-    #[view(getAcceptedPaymentToken)]
-    #[storage_mapper("acceptedPaymentTokenId")]
-    fn accepted_payment_token_id(&self) -> SingleValueMapper<TokenIdentifier>;
+        self.pending_tokens(&caller).clear();
+        for (token_id, nonce) in pending_tokens.iter() {
+            let amount = self.pending_balance(&caller, token_id, *nonce).get();
+            self.pending_balance(&caller, token_id, *nonce).clear();
+            if amount == 0 {
+                continue;
+            }
+            self.send()
+                .direct(&caller, token_id, *nonce, &amount, b"claim from gtw sc");
+            self.claim_event(&caller, token_id, *nonce, &amount);
+        }
 
-    #[view(getAcceptedFeesAddr)]
-    #[storage_mapper("acceptedFeesAddrId")]
-    fn accepted_fees_addr_id(&self) -> SingleValueMapper<ManagedAddress>;
+        Ok(())
+    }
 
-    #[view(getAcceptedRestAddr)]
-    #[storage_mapper("acceptedRestAddrId")]
-    fn accepted_rest_addr_id(&self) -> SingleValueMapper<ManagedAddress>;
+    /// Stops new payments until `unpause` is called: `sendToken`, `sendTokenReferred`,
+    /// `payMerchant`, `payMerchantReferred`, `payMerchantConverted`, `sendMultiToken`,
+    /// `sendNft`, `payInvoice`, `createEscrow` and `releaseEscrow`.
+    /// Refunds and withdrawals stay open: `refundPayment`, `cancelEscrow`, `reclaimEscrow` and `claim`.
+    /// Owner or pauser only.
+    #[endpoint]
+    fn pause(&self) -> SCResult<()> {
+        self.require_owner_or_pauser()?;
+        self.paused().set(&true);
+        self.pause_event(&self.blockchain().get_caller());
 
-    #[view(getMinAmount)]
-    #[storage_mapper("minAmount")]
-    fn min_amount(&self) -> SingleValueMapper<BigUint>;
+        Ok(())
+    }
 
-    
-    #[view(feesInPercent)]
-    #[storage_mapper("feesInPercent")]
-    fn fees_in_percent(&self) -> SingleValueMapper<BigUint>;
+    #[endpoint]
+    fn unpause(&self) -> SCResult<()> {
+        self.require_owner_or_pauser()?;
+        self.paused().clear();
+        self.unpause_event(&self.blockchain().get_caller());
+
+        Ok(())
+    }
+
+    /// Rejects every payment from `address`. Owner or compliance officer only.
+    #[endpoint(blockAddress)]
+    fn block_address(&self, address: ManagedAddress) -> SCResult<()> {
+        self.require_owner_or_compliance_officer()?;
+        require!(self.blocked_addresses().insert(address.clone()), "Address is already blocked");
+        self.address_blocked_event(&self.blockchain().get_caller(), &address);
+
+        Ok(())
+    }
+
+    #[endpoint(unblockAddress)]
+    fn unblock_address(&self, address: ManagedAddress) -> SCResult<()> {
+        self.require_owner_or_compliance_officer()?;
+        require!(self.blocked_addresses().remove(&address), "Address is not blocked");
+        self.address_unblocked_event(&self.blockchain().get_caller(), &address);
+
+        Ok(())
+    }
+
+    /// Approves `address` as a payer in allowlist mode. Owner or compliance officer only.
+    #[endpoint(allowPayer)]
+    fn allow_payer(&self, address: ManagedAddress) -> SCResult<()> {
+        self.require_owner_or_compliance_officer()?;
+        require!(self.allowed_payers().insert(address.clone()), "Payer is already allowed");
+        self.payer_allowed_event(&self.blockchain().get_caller(), &address);
+
+        Ok(())
+    }
+
+    #[endpoint(disallowPayer)]
+    fn disallow_payer(&self, address: ManagedAddress) -> SCResult<()> {
+        self.require_owner_or_compliance_officer()?;
+        require!(self.allowed_payers().remove(&address), "Payer is not allowed");
+        self.payer_disallowed_event(&self.blockchain().get_caller(), &address);
+
+        Ok(())
+    }
+
+    /// When enabled, only allowed payers can pay. Blocked addresses are rejected either way.
+    /// Owner or compliance officer only.
+    #[endpoint(setAllowlistMode)]
+    fn set_allowlist_mode(&self, allowlist_mode: bool) -> SCResult<()> {
+        self.require_owner_or_compliance_officer()?;
+        self.allowlist_mode().set(&allowlist_mode);
+        self.allowlist_mode_changed_event(&self.blockchain().get_caller(), allowlist_mode);
+
+        Ok(())
+    }
+
+    /// Submits `action` to the board, already signed by the proposer. Board member only.
+    /// Returns the proposal id.
+    #[endpoint]
+    fn propose(&self, action: BoardAction<Self::Api>) -> SCResult<u64> {
+        let caller = self.blockchain().get_caller();
+        self.require_board_member(&caller)?;
+
+        let proposal_id = self.last_proposal_id().get() + 1;
+        self.last_proposal_id().set(&proposal_id);
+        self.proposals(proposal_id).set(&Proposal {
+            proposer: caller.clone(),
+            action,
+        });
+        self.proposal_signers(proposal_id).insert(caller.clone());
+        self.pending_proposals().insert(proposal_id);
+        self.proposal_created_event(&caller, proposal_id);
+
+        Ok(proposal_id)
+    }
+
+    /// Board member only.
+    #[endpoint(signProposal)]
+    fn sign_proposal(&self, proposal_id: u64) -> SCResult<()> {
+        let caller = self.blockchain().get_caller();
+        self.require_board_member(&caller)?;
+        self.require_pending_proposal(proposal_id)?;
+        require!(
+            self.proposal_signers(proposal_id).insert(caller.clone()),
+            "Proposal is already signed"
+        );
+        self.proposal_signed_event(&caller, proposal_id);
+
+        Ok(())
+    }
+
+    /// Board member only.
+    #[endpoint(unsignProposal)]
+    fn unsign_proposal(&self, proposal_id: u64) -> SCResult<()> {
+        let caller = self.blockchain().get_caller();
+        self.require_board_member(&caller)?;
+        self.require_pending_proposal(proposal_id)?;
+        require!(
+            self.proposal_signers(proposal_id).remove(&caller),
+            "Proposal is not signed"
+        );
+        self.proposal_unsigned_event(&caller, proposal_id);
+
+        Ok(())
+    }
+
+    /// Applies a proposal signed by at least `quorum` current board members. Board member only.
+    #[endpoint(performProposal)]
+    fn perform_proposal(&self, proposal_id: u64) -> SCResult<()> {
+        let caller = self.blockchain().get_caller();
+        self.require_board_member(&caller)?;
+        self.require_pending_proposal(proposal_id)?;
+        require!(
+            self.proposal_signature_count(proposal_id) >= self.quorum().get(),
+            "Quorum is not reached"
+        );
+
+        let proposal = self.proposals(proposal_id).get();
+        self.remove_proposal(proposal_id);
+        self.perform_board_action(proposal.action)?;
+        self.proposal_performed_event(&caller, proposal_id);
+
+        Ok(())
+    }
+
+    /// Drops a proposal no current board member signs anymore. Board member only.
+    #[endpoint(discardProposal)]
+    fn discard_proposal(&self, proposal_id: u64) -> SCResult<()> {
+        let caller = self.blockchain().get_caller();
+        self.require_board_member(&caller)?;
+        self.require_pending_proposal(proposal_id)?;
+        require!(
+            self.proposal_signature_count(proposal_id) == 0,
+            "Proposal is still signed"
+        );
+        self.remove_proposal(proposal_id);
+        self.proposal_discarded_event(&caller, proposal_id);
+
+        Ok(())
+    }
 
+    // owner endpoints
+
+    #[only_owner]
+    #[endpoint(addPauser)]
+    fn add_pauser(&self, address: ManagedAddress) -> SCResult<()> {
+        require!(self.pausers().insert(address.clone()), "Address is already a pauser");
+        self.pauser_added_event(&address);
+
+        Ok(())
+    }
+
+    #[only_owner]
+    #[endpoint(removePauser)]
+    fn remove_pauser(&self, address: ManagedAddress) -> SCResult<()> {
+        require!(self.pausers().remove(&address), "Address is not a pauser");
+        self.pauser_removed_event(&address);
+
+        Ok(())
+    }
+
+    #[only_owner]
+    #[endpoint(addComplianceOfficer)]
+    fn add_compliance_officer(&self, address: ManagedAddress) -> SCResult<()> {
+        require!(
+            self.compliance_officers().insert(address.clone()),
+            "Address is already a compliance officer"
+        );
+        self.compliance_officer_added_event(&address);
+
+        Ok(())
+    }
+
+    #[only_owner]
+    #[endpoint(removeComplianceOfficer)]
+    fn remove_compliance_officer(&self, address: ManagedAddress) -> SCResult<()> {
+        require!(
+            self.compliance_officers().remove(&address),
+            "Address is not a compliance officer"
+        );
+        self.compliance_officer_removed_event(&address);
+
+        Ok(())
+    }
+
+    /// Hands the gateway configuration over to a board of `members`, `quorum` of which
+    /// must sign a proposal to apply it: accepted tokens and NFT collections, fees, fee tiers,
+    /// bounds and rounding, referrals, fee exemptions, fees and rest addresses, payout split,
+    /// merchants payout address, fee and settlement token, DEX pairs and fee conversion.
+    /// The owner endpoints of these are disabled from then on, see `BoardAction`,
+    /// and the board can only be changed through its own proposals.
+    /// The owner keeps pausers, compliance officers, payment limits, payout mode,
+    /// refund window and policy, escrow timeout, unique references, merchant activation,
+    /// and contract upgrades, which only migrate storage once the board exists.
+    #[only_owner]
+    #[endpoint(setupBoard)]
+    fn setup_board(
+        &self,
+        quorum: usize,
+        #[var_args] members: MultiArgVec<ManagedAddress>,
+    ) -> SCResult<()> {
+        self.require_board_inactive()?;
+        for member in members.into_vec() {
+            require!(
+                self.board_members().insert(member.clone()),
+                "Duplicate board member"
+            );
+            self.board_member_added_event(&member);
+        }
+        self.require_valid_quorum(quorum)?;
+        self.quorum().set(&quorum);
+        self.quorum_changed_event(quorum);
+
+        Ok(())
+    }
+
+    /// Chooses between sending payouts within each payment (`Push`, default)
+    /// and crediting them to balances withdrawn by the recipients with `claim` (`Pull`).
+    #[only_owner]
+    #[endpoint(setPayoutMode)]
+    fn set_payout_mode(&self, payout_mode: PayoutMode) -> SCResult<()> {
+        self.payout_mode().set(&payout_mode);
+        self.payout_mode_changed_event(&payout_mode);
+
+        Ok(())
+    }
+
+    /// Accepts `token_id` in `sendToken` with its own minimum amount and fee.
+    #[only_owner]
+    #[endpoint(addAcceptedToken)]
+    fn add_accepted_token(
+        &self,
+        token_id: TokenIdentifier,
+        min_amount: BigUint,
+        fee_basis_points: BigUint,
+    ) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::AddAcceptedToken(token_id, min_amount, fee_basis_points))
+    }
+
+    #[only_owner]
+    #[endpoint(removeAcceptedToken)]
+    fn remove_accepted_token(&self, token_id: TokenIdentifier) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::RemoveAcceptedToken(token_id))
+    }
+
+    /// Accepts NFTs of `collection` in `sendNft`, charging a flat `fee_amount` of `fee_token`.
+    /// Calling it again for the same collection updates the fee.
+    #[only_owner]
+    #[endpoint(setNftFlatFee)]
+    fn set_nft_flat_fee(
+        &self,
+        collection: TokenIdentifier,
+        fee_token: TokenIdentifier,
+        fee_amount: BigUint,
+    ) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetNftFlatFee(collection, fee_token, fee_amount))
+    }
+
+    #[only_owner]
+    #[endpoint(removeNftCollection)]
+    fn remove_nft_collection(&self, collection: TokenIdentifier) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::RemoveNftCollection(collection))
+    }
+
+    #[only_owner]
+    #[endpoint(setMinAmount)]
+    fn set_min_amount(&self, token_id: TokenIdentifier, min_amount: BigUint) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetMinAmount(token_id, min_amount))
+    }
+
+    #[only_owner]
+    #[endpoint(setFeeBasisPoints)]
+    fn set_fee_basis_points(
+        &self,
+        token_id: TokenIdentifier,
+        fee_basis_points: BigUint,
+    ) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetFeeBasisPoints(token_id, fee_basis_points))
+    }
+
+    /// Chooses how the fees of `token_id` are rounded, see `FeeRounding`.
+    #[only_owner]
+    #[endpoint(setFeeRounding)]
+    fn set_fee_rounding(&self, token_id: TokenIdentifier, fee_rounding: FeeRounding) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetFeeRounding(token_id, fee_rounding))
+    }
+
+    /// Sets amount bands with their own fee for `token_id`, as `threshold, fee_basis_points` pairs
+    /// with strictly increasing thresholds. A payment uses the first band whose threshold
+    /// is above its amount, larger payments use the base fee of the token.
+    /// E.g. `100, 300, 1000, 200` with a base fee of 100: 3% under 100, 2% under 1000, 1% above.
+    /// Calling it without tiers removes them.
+    #[only_owner]
+    #[endpoint(setFeeTiers)]
+    fn set_fee_tiers(
+        &self,
+        token_id: TokenIdentifier,
+        #[var_args] tiers: FeeTierArgs<Self::Api>,
+    ) -> SCResult<()> {
+        let fee_tiers = tiers
+            .into_vec()
+            .into_iter()
+            .map(|tier| {
+                let (threshold, fee_basis_points) = tier.into_tuple();
+                FeeTier {
+                    threshold,
+                    fee_basis_points,
+                }
+            })
+            .collect();
+        self.perform_owner_action(BoardAction::SetFeeTiers(token_id, fee_tiers))
+    }
+
+    /// Sets the minimum and maximum absolute fee for `token_id`, 0 meaning no bound.
+    /// The fee never exceeds the payment itself, even with a minimum fee.
+    #[only_owner]
+    #[endpoint(setFeeBounds)]
+    fn set_fee_bounds(
+        &self,
+        token_id: TokenIdentifier,
+        min_fee: BigUint,
+        max_fee: BigUint,
+    ) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetFeeBounds(token_id, min_fee, max_fee))
+    }
+
+    /// Sets the maximum amount of `token_id` in one payment, and the maximum amount one address
+    /// can pay over the last 24 hours (block timestamp), 0 meaning no limit.
+    /// Volumes are only tracked while a daily limit is set.
+    #[only_owner]
+    #[endpoint(setPaymentLimits)]
+    fn set_payment_limits(
+        &self,
+        token_id: TokenIdentifier,
+        max_payment_amount: BigUint,
+        daily_volume_limit: BigUint,
+    ) -> SCResult<()> {
+        self.require_accepted_token(&token_id)?;
+        require!(
+            max_payment_amount == 0 || max_payment_amount > self.min_amount(&token_id).get(),
+            "Max payment amount must be greater than the min_amount"
+        );
+
+        if max_payment_amount == 0 {
+            self.max_payment_amount(&token_id).clear();
+        } else {
+            self.max_payment_amount(&token_id).set(&max_payment_amount);
+        }
+        if daily_volume_limit == 0 {
+            self.daily_volume_limit(&token_id).clear();
+        } else {
+            self.daily_volume_limit(&token_id).set(&daily_volume_limit);
+        }
+        self.payment_limits_changed_event(&token_id, &max_payment_amount, &daily_volume_limit);
+
+        Ok(())
+    }
+
+    #[only_owner]
+    #[endpoint(registerReferrer)]
+    fn register_referrer(&self, referrer: ManagedAddress) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::RegisterReferrer(referrer))
+    }
+
+    /// Commissions already credited to the referrer can still be claimed.
+    #[only_owner]
+    #[endpoint(unregisterReferrer)]
+    fn unregister_referrer(&self, referrer: ManagedAddress) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::UnregisterReferrer(referrer))
+    }
+
+    /// Sets the share of the fee, in basis points of the fee, paid to the referrer of a payment.
+    /// Commissions are always credited to the referrer and withdrawn with `claim`.
+    #[only_owner]
+    #[endpoint(setReferralFeeShare)]
+    fn set_referral_fee_share(&self, referral_fee_share: BigUint) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetReferralFeeShare(referral_fee_share))
+    }
+
+    /// Charges payments from `address`, or to `address` as a payee, `fee_basis_points`
+    /// instead of the usual fee. 0 means no fee at all.
+    #[only_owner]
+    #[endpoint(setFeeExemption)]
+    fn set_fee_exemption(
+        &self,
+        address: ManagedAddress,
+        fee_basis_points: BigUint,
+    ) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetFeeExemption(address, fee_basis_points))
+    }
+
+    #[only_owner]
+    #[endpoint(removeFeeExemption)]
+    fn remove_fee_exemption(&self, address: ManagedAddress) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::RemoveFeeExemption(address))
+    }
+
+    /// Sets how long, in seconds, a payment can be refunded with `refundPayment`. 0 disables refunds.
+    #[only_owner]
+    #[endpoint(setRefundWindow)]
+    fn set_refund_window(&self, refund_window: u64) -> SCResult<()> {
+        self.refund_window().set(&refund_window);
+        self.refund_window_changed_event(refund_window);
+
+        Ok(())
+    }
+
+    #[only_owner]
+    #[endpoint(setRefundFeePolicy)]
+    fn set_refund_fee_policy(&self, refund_fee_policy: RefundFeePolicy) -> SCResult<()> {
+        self.refund_fee_policy().set(&refund_fee_policy);
+        self.refund_fee_policy_changed_event(&refund_fee_policy);
+
+        Ok(())
+    }
+
+    /// Sets how long, in seconds, an escrowed payment can only be released by its payer.
+    /// 0 disables `createEscrow`, held payments can still be released or cancelled.
+    #[only_owner]
+    #[endpoint(setEscrowTimeout)]
+    fn set_escrow_timeout(&self, escrow_timeout: u64) -> SCResult<()> {
+        self.escrow_timeout().set(&escrow_timeout);
+        self.escrow_timeout_changed_event(escrow_timeout);
+
+        Ok(())
+    }
+
+    /// When enabled, a payment whose reference was already used is rejected.
+    #[only_owner]
+    #[endpoint(setUniqueReferences)]
+    fn set_unique_references(&self, unique_references: bool) -> SCResult<()> {
+        self.unique_references().set(&unique_references);
+        self.unique_references_changed_event(unique_references);
+
+        Ok(())
+    }
+
+    #[only_owner]
+    #[endpoint(setFeesAddr)]
+    fn set_fees_addr(&self, fees_addr: ManagedAddress) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetFeesAddr(fees_addr))
+    }
+
+    #[only_owner]
+    #[endpoint(setRestAddr)]
+    fn set_rest_addr(&self, rest_addr: ManagedAddress) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetRestAddr(rest_addr))
+    }
+
+    /// Registers a merchant paid through `payMerchant`, active right away.
+    /// Optional `fee_basis_points` replaces the fee of the token for its payments.
+    /// Returns the merchant id.
+    #[only_owner]
+    #[endpoint(registerMerchant)]
+    fn register_merchant(
+        &self,
+        payout_address: ManagedAddress,
+        #[var_args] opt_fee_basis_points: OptionalArg<BigUint>,
+    ) -> SCResult<u64> {
+        self.require_board_inactive()?;
+        self.add_merchant(payout_address, opt_fee_basis_points.into_option())
+    }
+
+    #[only_owner]
+    #[endpoint(setMerchantPayoutAddress)]
+    fn set_merchant_payout_address(
+        &self,
+        merchant_id: u64,
+        payout_address: ManagedAddress,
+    ) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetMerchantPayoutAddress(merchant_id, payout_address))
+    }
+
+    /// Sets the fee override of a merchant, or removes it when called without `fee_basis_points`.
+    #[only_owner]
+    #[endpoint(setMerchantFeeBasisPoints)]
+    fn set_merchant_fee_basis_points(
+        &self,
+        merchant_id: u64,
+        #[var_args] opt_fee_basis_points: OptionalArg<BigUint>,
+    ) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetMerchantFeeBasisPoints(
+            merchant_id,
+            opt_fee_basis_points.into_option(),
+        ))
+    }
+
+    /// Sets the token merchant `merchant_id` is paid in through `payMerchantConverted`,
+    /// or removes it when called without `token_id`.
+    #[only_owner]
+    #[endpoint(setMerchantSettlementToken)]
+    fn set_merchant_settlement_token(
+        &self,
+        merchant_id: u64,
+        #[var_args] opt_token_id: OptionalArg<TokenIdentifier>,
+    ) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetMerchantSettlementToken(
+            merchant_id,
+            opt_token_id.into_option(),
+        ))
+    }
+
+    /// Sets the DEX pair contract used to swap `token_in` to `token_out`.
+    /// For EGLD payments, the pair of the wrapped EGLD token is used.
+    #[only_owner]
+    #[endpoint(setDexPair)]
+    fn set_dex_pair(
+        &self,
+        token_in: TokenIdentifier,
+        token_out: TokenIdentifier,
+        pair_address: ManagedAddress,
+    ) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetDexPair(token_in, token_out, pair_address))
+    }
+
+    #[only_owner]
+    #[endpoint(removeDexPair)]
+    fn remove_dex_pair(&self, token_in: TokenIdentifier, token_out: TokenIdentifier) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::RemoveDexPair(token_in, token_out))
+    }
+
+    /// Sets the contract wrapping EGLD payments into `wrapped_egld_token_id` before a swap.
+    #[only_owner]
+    #[endpoint(setEgldWrapper)]
+    fn set_egld_wrapper(
+        &self,
+        wrapper_address: ManagedAddress,
+        wrapped_egld_token_id: TokenIdentifier,
+    ) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetEgldWrapper(wrapper_address, wrapped_egld_token_id))
+    }
+
+    /// When enabled, converted payments also pay the fee in the settlement token.
+    #[only_owner]
+    #[endpoint(setConvertFees)]
+    fn set_convert_fees(&self, convert_fees: bool) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::SetConvertFees(convert_fees))
+    }
+
+    /// Inactive merchants cannot be paid nor create invoices.
+    #[only_owner]
+    #[endpoint(setMerchantActive)]
+    fn set_merchant_active(&self, merchant_id: u64, active: bool) -> SCResult<()> {
+        self.require_merchant_exists(merchant_id)?;
+        self.merchants(merchant_id)
+            .update(|merchant| merchant.active = active);
+        self.merchant_updated_event(merchant_id);
+
+        Ok(())
+    }
+
+    /// Splits the net amount of every payment to the gateway between `shares` proportionally
+    /// to their weights, instead of sending it all to the rest address.
+    /// `remainder_addr` must be one of the shares, it receives what is left after rounding
+    /// so that the parts always sum up to the net amount. It also receives single NFTs.
+    #[only_owner]
+    #[endpoint(setPayoutSplit)]
+    fn set_payout_split(
+        &self,
+        remainder_addr: ManagedAddress,
+        #[var_args] shares: MultiArgVec<MultiArg2<ManagedAddress, u32>>,
+    ) -> SCResult<()> {
+        let shares = shares
+            .into_vec()
+            .into_iter()
+            .map(|share| {
+                let (address, weight) = share.into_tuple();
+                PayoutShare { address, weight }
+            })
+            .collect();
+        self.perform_owner_action(BoardAction::SetPayoutSplit(remainder_addr, shares))
+    }
+
+    /// Removes the payout split, net amounts go to the rest address again.
+    #[only_owner]
+    #[endpoint(clearPayoutSplit)]
+    fn clear_payout_split(&self) -> SCResult<()> {
+        self.perform_owner_action(BoardAction::ClearPayoutSplit)
+    }
+
+    // views
+
+    /// Lists every accepted token with its minimum amount and fee in basis points.
+    #[view(getAcceptedTokens)]
+    fn get_accepted_tokens(&self) -> MultiResultVec<MultiResult3<TokenIdentifier, BigUint, BigUint>> {
+        let mut result = MultiResultVec::new();
+        for token_id in self.accepted_tokens().iter() {
+            let min_amount = self.min_amount(&token_id).get();
+            let fee_basis_points = self.fee_basis_points(&token_id).get();
+            result.push((token_id, min_amount, fee_basis_points).into());
+        }
+        result
+    }
+
+    /// Returns the token and amount of the flat fee charged for an NFT of `collection`.
+    #[view(getNftFlatFee)]
+    fn get_nft_flat_fee(&self, collection: TokenIdentifier) -> MultiResult2<TokenIdentifier, BigUint> {
+        (
+            self.nft_fee_token(&collection).get(),
+            self.nft_fee_amount(&collection).get(),
+        )
+            .into()
+    }
+
+    /// Returns the full fee schedule of `token_id` as `from_amount, fee_basis_points` bands:
+    /// a payment of at least `from_amount` and below the next band pays `fee_basis_points`.
+    #[view(getFeeSchedule)]
+    fn get_fee_schedule(
+        &self,
+        token_id: TokenIdentifier,
+    ) -> MultiResultVec<MultiResult2<BigUint, BigUint>> {
+        let mut result = MultiResultVec::new();
+        let mut from_amount = BigUint::zero();
+        for tier in self.fee_tiers(&token_id).iter() {
+            result.push((from_amount, tier.fee_basis_points).into());
+            from_amount = tier.threshold;
+        }
+        result.push((from_amount, self.fee_basis_points(&token_id).get()).into());
+        result
+    }
+
+    /// Lists the beneficiaries of the net amount with their weights.
+    /// Empty when the whole net amount goes to the rest address.
+    #[view(getPayoutSplit)]
+    fn get_payout_split(&self) -> MultiResultVec<MultiResult2<ManagedAddress, u32>> {
+        let mut result = MultiResultVec::new();
+        for share in self.payout_shares().iter() {
+            result.push((share.address, share.weight).into());
+        }
+        result
+    }
+
+    #[view(getInvoiceStatus)]
+    fn get_invoice_status(&self, invoice_id: u64) -> SCResult<InvoiceStatus> {
+        require!(!self.invoices(invoice_id).is_empty(), "Invoice does not exist");
+        Ok(self.invoice_status(&self.invoices(invoice_id).get()))
+    }
+
+    /// Lists the exempted addresses with their custom fee in basis points.
+    #[view(getFeeExemptions)]
+    fn get_fee_exemptions(&self) -> MultiResultVec<MultiResult2<ManagedAddress, BigUint>> {
+        let mut result = MultiResultVec::new();
+        for address in self.fee_exempt_addresses().iter() {
+            let fee_basis_points = self.exempted_fee_rate(&address).get();
+            result.push((address, fee_basis_points).into());
+        }
+        result
+    }
+
+    /// Returns the fee, in basis points, `payer` would be charged for paying `amount` of `token_id`
+    /// to merchant `merchant_id` (default `0`, the gateway), before the fee bounds of the token.
+    #[view(getEffectiveFeeRate)]
+    fn get_effective_fee_rate(
+        &self,
+        payer: ManagedAddress,
+        token_id: TokenIdentifier,
+        amount: BigUint,
+        #[var_args] opt_merchant_id: OptionalArg<u64>,
+    ) -> BigUint {
+        let merchant_id = opt_merchant_id.into_option().unwrap_or(GATEWAY_MERCHANT_ID);
+        match self.exempted_fee_basis_points(&payer, merchant_id) {
+            Some(fee_basis_points) => fee_basis_points,
+            None => self.fee_basis_points_for_payment(merchant_id, &token_id, &amount),
+        }
+    }
+
+    /// Returns the largest amount of `token_id` `address` can pay right now,
+    /// within the max payment amount and what is left of its daily volume.
+    /// Nothing when `token_id` has no limit.
+    #[view(getRemainingAllowance)]
+    fn get_remaining_allowance(
+        &self,
+        address: ManagedAddress,
+        token_id: TokenIdentifier,
+    ) -> OptionalResult<BigUint> {
+        let mut opt_allowance = None;
+        if !self.daily_volume_limit(&token_id).is_empty() {
+            let now = self.blockchain().get_block_timestamp();
+            let (_, expired_volume) = self.expired_volume(&address, &token_id, now);
+            let volume = self.payer_volume(&address, &token_id).get() - expired_volume;
+            let limit = self.daily_volume_limit(&token_id).get();
+            opt_allowance = Some(if volume < limit {
+                limit - volume
+            } else {
+                BigUint::zero()
+            });
+        }
+        if !self.max_payment_amount(&token_id).is_empty() {
+            let max_payment_amount = self.max_payment_amount(&token_id).get();
+            opt_allowance = match opt_allowance {
+                Some(allowance) if allowance < max_payment_amount => Some(allowance),
+                _ => Some(max_payment_amount),
+            };
+        }
+        opt_allowance.into()
+    }
+
+    /// Payment count, gross volume, fees collected and net amount paid out in `token_id`,
+    /// over all payments.
+    #[view(getTokenStats)]
+    fn get_token_stats(&self, token_id: TokenIdentifier) -> PaymentStats<Self::Api> {
+        self.stats_or_zero(&self.token_stats(&token_id))
+    }
+
+    /// Same as `getTokenStats`, for the payments to one merchant.
+    #[view(getMerchantStats)]
+    fn get_merchant_stats(
+        &self,
+        merchant_id: u64,
+        token_id: TokenIdentifier,
+    ) -> PaymentStats<Self::Api> {
+        self.stats_or_zero(&self.merchant_stats(merchant_id, &token_id))
+    }
+
+    /// Returns true if a payment was made with `reference`.
+    #[view(isReferencePaid)]
+    fn is_reference_paid(&self, reference: ManagedBuffer) -> bool {
+        !self.payment_id_by_reference(&reference).is_empty()
+    }
+
+    /// Lists the balances `address` can withdraw with `claim`.
+    #[view(getPendingBalances)]
+    fn get_pending_balances(
+        &self,
+        address: ManagedAddress,
+    ) -> MultiResultVec<MultiResult3<TokenIdentifier, u64, BigUint>> {
+        let mut result = MultiResultVec::new();
+        for (token_id, nonce) in self.pending_tokens(&address).iter() {
+            let amount = self.pending_balance(&address, &token_id, nonce).get();
+            result.push((token_id, nonce, amount).into());
+        }
+        result
+    }
+
+    /// Lists the proposals waiting for the board, with the number of current members signing them.
+    #[view(getPendingProposals)]
+    fn get_pending_proposals(&self) -> MultiResultVec<MultiResult3<u64, Proposal<Self::Api>, usize>> {
+        let mut result = MultiResultVec::new();
+        for proposal_id in self.pending_proposals().iter() {
+            result.push(
+                (
+                    proposal_id,
+                    self.proposals(proposal_id).get(),
+                    self.proposal_signature_count(proposal_id),
+                )
+                    .into(),
+            );
+        }
+        result
+    }
+
+    // private
+
+    /// Processes a payment sent to `sendToken`, `payMerchant` or their referred variants.
+    fn process_direct_payment(
+        &self,
+        merchant_id: u64,
+        payment_token: TokenIdentifier,
+        payment_nonce: u64,
+        payment_amount: BigUint,
+        opt_reference: OptionalArg<ManagedBuffer>,
+        opt_referrer: Option<ManagedAddress>,
+    ) -> SCResult<()> {
+        self.require_not_paused()?;
+        require!(
+            self.call_value().esdt_token_type() != EsdtTokenType::NonFungible,
+            "NFT payments must use sendNft"
+        );
+        self.process_single_payment(&PaymentRequest {
+            payer: self.blockchain().get_caller(),
+            merchant_id,
+            token_id: payment_token,
+            nonce: payment_nonce,
+            amount: payment_amount,
+            reference: opt_reference.into_option().unwrap_or_else(ManagedBuffer::new),
+            referrer: opt_referrer,
+            min_amount_out: None,
+            allowance_consumed: false,
+        })?;
+
+        Ok(())
+    }
+
+    /// Processes one payment and executes its payouts right away.
+    fn process_single_payment(&self, request: &PaymentRequest<Self::Api>) -> SCResult<u64> {
+        let mut payouts = Vec::new();
+        let payment_id = self.process_payment(request, &mut payouts)?;
+        self.execute_payouts(payouts, &request.reference);
+        Ok(payment_id)
+    }
+
+    /// Checks a fungible payment against the token configuration, adds the fee
+    /// and the net amount to `payouts`, records the payment and emits the `payment` event.
+    /// The net amount goes to the merchant, or is distributed by the gateway for `GATEWAY_MERCHANT_ID`.
+    /// Returns the id of the recorded payment.
+    fn process_payment(
+        &self,
+        request: &PaymentRequest<Self::Api>,
+        payouts: &mut Vec<PendingPayout<Self::Api>>,
+    ) -> SCResult<u64> {
+        let payer = &request.payer;
+        let merchant_id = request.merchant_id;
+        let token_id = &request.token_id;
+        let nonce = request.nonce;
+        let amount = &request.amount;
+        let reference = &request.reference;
+        let opt_referrer = &request.referrer;
+
+        self.require_allowed_payer(payer)?;
+        self.require_valid_payment(merchant_id, token_id, amount, reference)?;
+        if !request.allowance_consumed {
+            self.consume_payment_allowance(payer, token_id, amount)?;
+        }
+        if let Some(referrer) = opt_referrer {
+            require!(self.referrers().contains(referrer), "Unknown referrer");
+            require!(referrer != payer, "Payer cannot be its own referrer");
+        }
+
+        let amount_fees = self.fees_for_payment(payer, merchant_id, token_id, amount);
+        let amount_rest = amount.clone() - amount_fees.clone();
+
+        let mut referral_commission = BigUint::zero();
+        if let Some(referrer) = opt_referrer {
+            referral_commission = amount_fees.clone() * self.referral_fee_share().get()
+                / BigUint::from(FEE_DENOMINATOR);
+            if referral_commission > 0 {
+                self.credit_pending_balance(referrer, token_id, nonce, &referral_commission);
+                self.referrer_earnings(referrer, token_id)
+                    .update(|earnings| *earnings += &referral_commission);
+            }
+        }
+
+        let mut fee_payout =
+            EsdtTokenPayment::new(token_id.clone(), nonce, amount_fees.clone() - referral_commission.clone());
+        let mut net_payout = EsdtTokenPayment::new(token_id.clone(), nonce, amount_rest.clone());
+        if let Some(min_amount_out) = &request.min_amount_out {
+            self.convert_to_settlement_token(merchant_id, &mut fee_payout, &mut net_payout, min_amount_out)?;
+        }
+
+        let fees_addr = self.accepted_fees_addr_id().get();
+        payouts.push(PendingPayout {
+            to: fees_addr.clone(),
+            token_id: fee_payout.token_identifier,
+            nonce: fee_payout.token_nonce,
+            amount: fee_payout.amount,
+            data: self.transfer_data(b"fees from gtw sc", reference),
+        });
+        let payment_data = self.transfer_data(b"payment from gtw sc", reference);
+        let payout_token_id = net_payout.token_identifier.clone();
+        let payout_amount = net_payout.amount.clone();
+        let recipients = if merchant_id == GATEWAY_MERCHANT_ID {
+            self.distribute_net_amount(
+                &net_payout.token_identifier,
+                net_payout.token_nonce,
+                &net_payout.amount,
+                &payment_data,
+                payouts,
+            )
+        } else {
+            let payout_address = self.merchants(merchant_id).get().payout_address;
+            payouts.push(PendingPayout {
+                to: payout_address.clone(),
+                token_id: net_payout.token_identifier,
+                nonce: net_payout.token_nonce,
+                amount: net_payout.amount.clone(),
+                data: payment_data,
+            });
+            alloc::vec![Payout {
+                address: payout_address,
+                amount: net_payout.amount,
+            }]
+        };
+
+        let payment_id = self.record_payment(&PaymentRecord {
+            payer: payer.clone(),
+            merchant_id,
+            token_id: token_id.clone(),
+            nonce,
+            gross_amount: amount.clone(),
+            fee_token_id: token_id.clone(),
+            fee_amount: amount_fees.clone(),
+            payout_token_id: payout_token_id.clone(),
+            payout_amount: payout_amount.clone(),
+            reference: reference.clone(),
+            timestamp: self.blockchain().get_block_timestamp(),
+            refunded_amount: BigUint::zero(),
+        });
+        if payout_token_id == *token_id {
+            self.update_stats(merchant_id, token_id, amount, &amount_fees, &amount_rest);
+        } else {
+            self.update_stats(merchant_id, token_id, amount, &amount_fees, &BigUint::zero());
+            self.add_net_paid_out(merchant_id, &payout_token_id, &payout_amount);
+        }
+        if let Some(referrer) = opt_referrer {
+            if referral_commission > 0 {
+                self.referral_commission_event(referrer, payment_id, token_id, &referral_commission);
+            }
+        }
+
+        self.payment_event(
+            payer,
+            token_id,
+            nonce,
+            &PaymentEvent {
+                payment_id,
+                merchant_id,
+                reference: reference.clone(),
+                gross_amount: amount.clone(),
+                fee_token_id: token_id.clone(),
+                fee_amount: amount_fees,
+                net_amount: amount_rest,
+                fees_addr,
+                payout_token_id,
+                recipients,
+            },
+        );
+
+        Ok(payment_id)
+    }
+
+    /// Swaps the net payout, and the fee payout when fee conversion is enabled,
+    /// to the settlement token of the merchant. Amounts already in that token are kept.
+    fn convert_to_settlement_token(
+        &self,
+        merchant_id: u64,
+        fee_payout: &mut EsdtTokenPayment<Self::Api>,
+        net_payout: &mut EsdtTokenPayment<Self::Api>,
+        min_amount_out: &BigUint,
+    ) -> SCResult<()> {
+        let settlement_token_id = self.merchant_settlement_token(merchant_id).get();
+        if net_payout.token_identifier == settlement_token_id {
+            require!(net_payout.amount >= *min_amount_out, "Net amount is below min amount out");
+            return Ok(());
+        }
+
+        let convert_fees = self.convert_fees().get();
+        let mut amount_in = net_payout.amount.clone();
+        if convert_fees {
+            amount_in += &fee_payout.amount;
+        }
+        let amount_out = self.swap_tokens(
+            &net_payout.token_identifier,
+            &amount_in,
+            &settlement_token_id,
+            min_amount_out,
+        )?;
+
+        if convert_fees {
+            let fee_amount_out = amount_out.clone() * fee_payout.amount.clone() / amount_in;
+            *net_payout = EsdtTokenPayment::new(
+                settlement_token_id.clone(),
+                0,
+                amount_out - fee_amount_out.clone(),
+            );
+            *fee_payout = EsdtTokenPayment::new(settlement_token_id, 0, fee_amount_out);
+        } else {
+            *net_payout = EsdtTokenPayment::new(settlement_token_id, 0, amount_out);
+        }
+        Ok(())
+    }
+
+    /// Swaps `amount_in` of `token_in` through the configured DEX pair, wrapping EGLD first.
+    /// Returns the amount of `token_out` received.
+    fn swap_tokens(
+        &self,
+        token_in: &TokenIdentifier,
+        amount_in: &BigUint,
+        token_out: &TokenIdentifier,
+        min_amount_out: &BigUint,
+    ) -> SCResult<BigUint> {
+        let mut token_in = token_in.clone();
+        if token_in.is_egld() {
+            require!(!self.egld_wrapper_address().is_empty(), "EGLD wrapper is not set");
+            self.egld_wrapper_proxy(self.egld_wrapper_address().get())
+                .wrap_egld(amount_in.clone())
+                .execute_on_dest_context();
+            token_in = self.wrapped_egld_token_id().get();
+        }
+
+        require!(!self.dex_pair(&token_in, token_out).is_empty(), "Unknown DEX pair");
+        let balance_before = self.blockchain().get_sc_balance(token_out, 0);
+        self.dex_pair_proxy(self.dex_pair(&token_in, token_out).get())
+            .swap_tokens_fixed_input(token_in, 0, amount_in.clone(), token_out.clone(), min_amount_out.clone())
+            .execute_on_dest_context();
+        let amount_out = self.blockchain().get_sc_balance(token_out, 0) - balance_before;
+        require!(amount_out >= *min_amount_out, "Swap output is below min amount out");
+
+        Ok(amount_out)
+    }
+
+    fn require_valid_payment(
+        &self,
+        merchant_id: u64,
+        token_id: &TokenIdentifier,
+        amount: &BigUint,
+        reference: &ManagedBuffer,
+    ) -> SCResult<()> {
+        require!(
+            self.accepted_tokens().contains(token_id),
+            "Invalid payment token"
+        );
+        require!(
+            *amount > self.min_amount(token_id).get(),
+            "The payment must be greater than the min_amount"
+        );
+        self.require_valid_reference(reference)?;
+        if merchant_id != GATEWAY_MERCHANT_ID {
+            self.require_active_merchant(merchant_id)?;
+        }
+        Ok(())
+    }
+
+    /// Checks `amount` against the payment limits of `token_id`
+    /// and adds it to the daily volume of `payer`.
+    fn consume_payment_allowance(
+        &self,
+        payer: &ManagedAddress,
+        token_id: &TokenIdentifier,
+        amount: &BigUint,
+    ) -> SCResult<()> {
+        if !self.max_payment_amount(token_id).is_empty() {
+            require!(
+                *amount <= self.max_payment_amount(token_id).get(),
+                "The payment exceeds the max payment amount"
+            );
+        }
+        if self.daily_volume_limit(token_id).is_empty() {
+            return Ok(());
+        }
+
+        let now = self.blockchain().get_block_timestamp();
+        let (first_live_index, expired_volume) = self.expired_volume(payer, token_id, now);
+        let mut entries = self.payer_volume_entries(payer, token_id);
+        for index in self.payer_volume_expired_count(payer, token_id).get() + 1..first_live_index {
+            entries.clear_entry(index);
+        }
+        self.payer_volume_expired_count(payer, token_id)
+            .set(&(first_live_index - 1));
+
+        let volume = self.payer_volume(payer, token_id).get() - expired_volume + amount;
+        require!(
+            volume <= self.daily_volume_limit(token_id).get(),
+            "The payment exceeds the daily volume limit"
+        );
+        self.payer_volume(payer, token_id).set(&volume);
+        entries.push(&VolumeEntry {
+            timestamp: now,
+            amount: amount.clone(),
+        });
+        Ok(())
+    }
+
+    /// Walks the volume entries of `payer` that are no longer counted at `now`.
+    /// Returns the index of the first entry still in the window, and the volume that expired.
+    fn expired_volume(
+        &self,
+        payer: &ManagedAddress,
+        token_id: &TokenIdentifier,
+        now: u64,
+    ) -> (usize, BigUint) {
+        let entries = self.payer_volume_entries(payer, token_id);
+        let mut index = self.payer_volume_expired_count(payer, token_id).get() + 1;
+        let mut expired_volume = BigUint::zero();
+        while index <= entries.len() {
+            let entry = entries.get(index);
+            if entry.timestamp + DAILY_VOLUME_WINDOW > now {
+                break;
+            }
+            expired_volume += entry.amount;
+            index += 1;
+        }
+        (index, expired_volume)
+    }
+
+    /// Address the net amount of payments to `merchant_id` belongs to.
+    fn payee_addr(&self, merchant_id: u64) -> ManagedAddress {
+        if merchant_id == GATEWAY_MERCHANT_ID {
+            self.accepted_rest_addr_id().get()
+        } else {
+            self.merchants(merchant_id).get().payout_address
+        }
+    }
+
+    fn invoice_status(&self, invoice: &Invoice<Self::Api>) -> InvoiceStatus {
+        if invoice.status == InvoiceStatus::Open
+            && self.blockchain().get_block_timestamp() >= invoice.expiry
+        {
+            InvoiceStatus::Expired
+        } else {
+            invoice.status
+        }
+    }
+
+    fn update_stats(
+        &self,
+        merchant_id: u64,
+        token_id: &TokenIdentifier,
+        gross_amount: &BigUint,
+        fee_amount: &BigUint,
+        net_amount: &BigUint,
+    ) {
+        let mut token_stats = self.stats_or_zero(&self.token_stats(token_id));
+        self.add_to_stats(&mut token_stats, gross_amount, fee_amount, net_amount);
+        self.token_stats(token_id).set(&token_stats);
+
+        if merchant_id != GATEWAY_MERCHANT_ID {
+            let mut merchant_stats = self.stats_or_zero(&self.merchant_stats(merchant_id, token_id));
+            self.add_to_stats(&mut merchant_stats, gross_amount, fee_amount, net_amount);
+            self.merchant_stats(merchant_id, token_id).set(&merchant_stats);
+        }
+    }
+
+    /// Adds the net amount of a payment converted to `token_id`, without counting a payment.
+    fn add_net_paid_out(&self, merchant_id: u64, token_id: &TokenIdentifier, net_amount: &BigUint) {
+        let mut token_stats = self.stats_or_zero(&self.token_stats(token_id));
+        token_stats.net_paid_out += net_amount;
+        self.token_stats(token_id).set(&token_stats);
+
+        if merchant_id != GATEWAY_MERCHANT_ID {
+            let mut merchant_stats = self.stats_or_zero(&self.merchant_stats(merchant_id, token_id));
+            merchant_stats.net_paid_out += net_amount;
+            self.merchant_stats(merchant_id, token_id).set(&merchant_stats);
+        }
+    }
+
+    fn add_to_stats(
+        &self,
+        stats: &mut PaymentStats<Self::Api>,
+        gross_amount: &BigUint,
+        fee_amount: &BigUint,
+        net_amount: &BigUint,
+    ) {
+        stats.payment_count += 1;
+        stats.gross_volume += gross_amount;
+        stats.fees_collected += fee_amount;
+        stats.net_paid_out += net_amount;
+    }
+
+    fn stats_or_zero(
+        &self,
+        mapper: &SingleValueMapper<PaymentStats<Self::Api>>,
+    ) -> PaymentStats<Self::Api> {
+        if mapper.is_empty() {
+            PaymentStats {
+                payment_count: 0,
+                gross_volume: BigUint::zero(),
+                fees_collected: BigUint::zero(),
+                net_paid_out: BigUint::zero(),
+            }
+        } else {
+            mapper.get()
+        }
+    }
+
+    fn record_payment(&self, payment: &PaymentRecord<Self::Api>) -> u64 {
+        let payment_id = self.last_payment_id().get() + 1;
+        self.last_payment_id().set(&payment_id);
+        self.payments(payment_id).set(payment);
+        if !payment.reference.is_empty() {
+            self.payment_id_by_reference(&payment.reference).set(&payment_id);
+        }
+        payment_id
+    }
+
+    fn require_valid_reference(&self, reference: &ManagedBuffer) -> SCResult<()> {
+        if !reference.is_empty() && self.unique_references().get() {
+            require!(
+                self.payment_id_by_reference(reference).is_empty(),
+                "Reference already paid"
+            );
+            require!(
+                self.escrow_id_by_reference(reference).is_empty(),
+                "Reference reserved by an escrow"
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether a held payment would pass the payment checks if it was released now.
+    /// Its reference is reserved and its limits were consumed when it was created.
+    /// Returns a bool rather than failing, `require!` ends the transaction right away.
+    fn is_releasable_escrow(&self, escrow: &Escrow<Self::Api>) -> bool {
+        let payer_allowed = !self.blocked_addresses().contains(&escrow.payer)
+            && (!self.allowlist_mode().get() || self.allowed_payers().contains(&escrow.payer));
+        let merchant_active = escrow.merchant_id == GATEWAY_MERCHANT_ID
+            || (!self.merchants(escrow.merchant_id).is_empty()
+                && self.merchants(escrow.merchant_id).get().active);
+        payer_allowed
+            && merchant_active
+            && self.accepted_tokens().contains(&escrow.token_id)
+            && escrow.amount > self.min_amount(&escrow.token_id).get()
+    }
+
+    /// Marks a held payment as cancelled and sends it back to its payer.
+    fn refund_escrow(&self, escrow_id: u64, escrow: &mut Escrow<Self::Api>) {
+        self.free_escrow_reference(escrow_id, &escrow.reference);
+        escrow.status = EscrowStatus::Cancelled;
+        self.escrows(escrow_id).set(&*escrow);
+        self.pay_out(
+            &escrow.payer,
+            &escrow.token_id,
+            escrow.nonce,
+            &escrow.amount,
+            &self.transfer_data(b"refund from gtw sc", &escrow.reference),
+        );
+    }
+
+    fn free_escrow_reference(&self, escrow_id: u64, reference: &ManagedBuffer) {
+        if !reference.is_empty() && self.escrow_id_by_reference(reference).get() == escrow_id {
+            self.escrow_id_by_reference(reference).clear();
+        }
+    }
+
+    /// Transfer data of a payout, followed by the payment reference if there is one.
+    fn transfer_data(&self, description: &[u8], reference: &ManagedBuffer) -> ManagedBuffer {
+        let mut data = ManagedBuffer::new_from_bytes(description);
+        if !reference.is_empty() {
+            data.append_bytes(b" ");
+            data.append(reference);
+        }
+        data
+    }
+
+    /// Sends `amount` to `to`, or credits it to their pending balance in `Pull` payout mode.
+    fn pay_out(
+        &self,
+        to: &ManagedAddress,
+        token_id: &TokenIdentifier,
+        nonce: u64,
+        amount: &BigUint,
+        data: &ManagedBuffer,
+    ) {
+        if *amount == 0 {
+            return;
+        }
+        match self.payout_mode().get() {
+            PayoutMode::Push => {
+                self.send().direct(to, token_id, nonce, amount, data.clone());
+            },
+            PayoutMode::Pull => {
+                self.credit_pending_balance(to, token_id, nonce, amount);
+            },
+        }
+    }
+
+    /// Sends, or credits in `Pull` payout mode, the payouts of processed payments.
+    /// ESDTs owed to the same recipient are grouped in one multi-transfer,
+    /// whose data carries `reference`, the reference of every payment in `payouts`.
+    fn execute_payouts(&self, payouts: Vec<PendingPayout<Self::Api>>, reference: &ManagedBuffer) {
+        if self.payout_mode().get() == PayoutMode::Pull {
+            for payout in payouts.iter() {
+                self.credit_pending_balance(&payout.to, &payout.token_id, payout.nonce, &payout.amount);
+            }
+            return;
+        }
+
+        let mut batches: Vec<(ManagedAddress, Vec<PendingPayout<Self::Api>>)> = Vec::new();
+        for payout in payouts {
+            if payout.amount == 0 {
+                continue;
+            }
+            if payout.token_id.is_egld() {
+                self.send().direct(&payout.to, &payout.token_id, 0, &payout.amount, payout.data);
+                continue;
+            }
+            match batches.iter_mut().find(|(to, _)| *to == payout.to) {
+                Some((_, batch)) => batch.push(payout),
+                None => {
+                    let mut batch = Vec::new();
+                    let to = payout.to.clone();
+                    batch.push(payout);
+                    batches.push((to, batch));
+                },
+            }
+        }
+
+        for (to, mut batch) in batches {
+            if batch.len() == 1 {
+                if let Some(payout) = batch.pop() {
+                    self.send().direct(&to, &payout.token_id, payout.nonce, &payout.amount, payout.data);
+                }
+                continue;
+            }
+
+            let mut transfers = ManagedVec::new();
+            for payout in batch {
+                transfers.push(EsdtTokenPayment::new(payout.token_id, payout.nonce, payout.amount));
+            }
+            let _ = Self::Api::send_api_impl().direct_multi_esdt_transfer_execute(
+                &to,
+                &transfers,
+                0,
+                &self.transfer_data(b"payments from gtw sc", reference),
+                &ManagedArgBuffer::new_empty(),
+            );
+        }
+    }
+
+    fn credit_pending_balance(
+        &self,
+        to: &ManagedAddress,
+        token_id: &TokenIdentifier,
+        nonce: u64,
+        amount: &BigUint,
+    ) {
+        if *amount == 0 {
+            return;
+        }
+        self.pending_tokens(to).insert((token_id.clone(), nonce));
+        self.pending_balance(to, token_id, nonce)
+            .update(|balance| *balance += amount);
+    }
+
+    /// Applies `fee_basis_points` to `amount` with the fee rounding of the token, within its fee bounds.
+    /// Fractions kept by `Accumulate` rounding only change when the bounds leave the fee as rounded.
+    fn compute_fees(
+        &self,
+        token_id: &TokenIdentifier,
+        amount: &BigUint,
+        fee_basis_points: &BigUint,
+    ) -> BigUint {
+        let (rounded_fees, fee_remainder) = self.apply_fee_rate(token_id, amount, fee_basis_points);
+        let mut fees = rounded_fees.clone();
+
+        if !self.min_fee(token_id).is_empty() {
+            let min_fee = self.min_fee(token_id).get();
+            if fees < min_fee {
+                fees = min_fee;
+            }
+        }
+        if !self.max_fee(token_id).is_empty() {
+            let max_fee = self.max_fee(token_id).get();
+            if fees > max_fee {
+                fees = max_fee;
+            }
+        }
+        if fees > *amount {
+            fees = amount.clone();
+        }
+        if fees == rounded_fees {
+            self.keep_fee_remainder(token_id, fee_remainder);
+        }
+
+        fees
+    }
+
+    /// Fee of a payment. Exempted payers and payees pay their custom rate,
+    /// without the fee bounds of the token.
+    fn fees_for_payment(
+        &self,
+        payer: &ManagedAddress,
+        merchant_id: u64,
+        token_id: &TokenIdentifier,
+        amount: &BigUint,
+    ) -> BigUint {
+        match self.exempted_fee_basis_points(payer, merchant_id) {
+            Some(fee_basis_points) => {
+                let (fees, fee_remainder) =
+                    self.apply_fee_rate(token_id, amount, &fee_basis_points);
+                self.keep_fee_remainder(token_id, fee_remainder);
+                fees
+            },
+            None => {
+                let fee_basis_points =
+                    self.fee_basis_points_for_payment(merchant_id, token_id, amount);
+                self.compute_fees(token_id, amount, &fee_basis_points)
+            },
+        }
+    }
+
+    /// Custom rate of the payer if it is exempted, otherwise of the payee.
+    fn exempted_fee_basis_points(
+        &self,
+        payer: &ManagedAddress,
+        merchant_id: u64,
+    ) -> Option<BigUint> {
+        if self.fee_exempt_addresses().contains(payer) {
+            return Some(self.exempted_fee_rate(payer).get());
+        }
+        let payee = self.payee_addr(merchant_id);
+        if self.fee_exempt_addresses().contains(&payee) {
+            return Some(self.exempted_fee_rate(&payee).get());
+        }
+        None
+    }
+
+    /// Fee of a payment: the merchant override if there is one, otherwise the fee tier
+    /// matching `amount`, otherwise the base fee of the token.
+    fn fee_basis_points_for_payment(
+        &self,
+        merchant_id: u64,
+        token_id: &TokenIdentifier,
+        amount: &BigUint,
+    ) -> BigUint {
+        if merchant_id != GATEWAY_MERCHANT_ID {
+            if let Some(fee_basis_points) = self.merchants(merchant_id).get().fee_basis_points {
+                return fee_basis_points;
+            }
+        }
+        self.fee_basis_points_for_amount(token_id, amount)
+    }
+
+    fn fee_basis_points_for_amount(&self, token_id: &TokenIdentifier, amount: &BigUint) -> BigUint {
+        for tier in self.fee_tiers(token_id).iter() {
+            if *amount < tier.threshold {
+                return tier.fee_basis_points;
+            }
+        }
+        self.fee_basis_points(token_id).get()
+    }
+
+    /// Adds the net amount of a payment for the rest address to `payouts`, or splits it
+    /// by weight when a payout split is configured. Returns what each recipient gets.
+    fn distribute_net_amount(
+        &self,
+        token_id: &TokenIdentifier,
+        nonce: u64,
+        net_amount: &BigUint,
+        data: &ManagedBuffer,
+        payouts: &mut Vec<PendingPayout<Self::Api>>,
+    ) -> Vec<Payout<Self::Api>> {
+        let mut recipients = Vec::new();
+        if self.payout_shares().is_empty() {
+            let rest_addr = self.accepted_rest_addr_id().get();
+            payouts.push(PendingPayout {
+                to: rest_addr.clone(),
+                token_id: token_id.clone(),
+                nonce,
+                amount: net_amount.clone(),
+                data: data.clone(),
+            });
+            recipients.push(Payout {
+                address: rest_addr,
+                amount: net_amount.clone(),
+            });
+            return recipients;
+        }
+
+        let remainder_addr = self.payout_remainder_addr().get();
+        let total_weight: u64 = self
+            .payout_shares()
+            .iter()
+            .map(|share| share.weight as u64)
+            .sum();
+
+        let mut distributed = BigUint::zero();
+        for share in self.payout_shares().iter() {
+            if share.address == remainder_addr {
+                continue;
+            }
+            let part = net_amount.clone() * BigUint::from(share.weight) / BigUint::from(total_weight);
+            if part > 0 {
+                payouts.push(PendingPayout {
+                    to: share.address.clone(),
+                    token_id: token_id.clone(),
+                    nonce,
+                    amount: part.clone(),
+                    data: data.clone(),
+                });
+                distributed += &part;
+                recipients.push(Payout {
+                    address: share.address,
+                    amount: part,
+                });
+            }
+        }
+
+        let remainder = net_amount.clone() - distributed;
+        if remainder > 0 {
+            payouts.push(PendingPayout {
+                to: remainder_addr.clone(),
+                token_id: token_id.clone(),
+                nonce,
+                amount: remainder.clone(),
+                data: data.clone(),
+            });
+            recipients.push(Payout {
+                address: remainder_addr,
+                amount: remainder,
+            });
+        }
+
+        recipients
+    }
+
+    /// Address receiving payments that cannot be split, like single NFTs.
+    fn main_payout_addr(&self) -> ManagedAddress {
+        if self.payout_remainder_addr().is_empty() {
+            self.accepted_rest_addr_id().get()
+        } else {
+            self.payout_remainder_addr().get()
+        }
+    }
+
+    /// Whether `init` runs as an upgrade. Deployments older than `schemaVersion` are recognized
+    /// by their fees address, set on every deploy.
+    fn is_deployed(&self) -> bool {
+        !self.schema_version().is_empty() || !self.accepted_fees_addr_id().is_empty()
+    }
+
+    /// Runs every migration step from the stored schema version up to `SCHEMA_VERSION`.
+    /// Deployments without a stored version are at 0, or at 1 once `feesInPercent` is gone.
+    fn migrate_storage(&self) -> SCResult<()> {
+        let from_version = if !self.schema_version().is_empty() {
+            self.schema_version().get()
+        } else if !self.legacy_fees_in_percent().is_empty() {
+            0
+        } else {
+            1
+        };
+        require!(
+            from_version <= SCHEMA_VERSION,
+            "Cannot downgrade the storage schema"
+        );
+
+        for version in from_version..SCHEMA_VERSION {
+            match version {
+                0 => self.migrate_fees_in_percent(),
+                1 => self.migrate_legacy_payment_token()?,
+                _ => return sc_error!("Unknown schema version"),
+            }
+        }
+        self.schema_version().set(&SCHEMA_VERSION);
+        if from_version != SCHEMA_VERSION {
+            self.storage_migrated_event(from_version, SCHEMA_VERSION);
+        }
+        Ok(())
+    }
+
+    /// 0 to 1: the whole-percent fee under `feesInPercent` into basis points.
+    fn migrate_fees_in_percent(&self) {
+        if !self.legacy_fees_in_percent().is_empty() {
+            let fee_basis_points =
+                self.legacy_fees_in_percent().get() * BigUint::from(BASIS_POINTS_PER_PERCENT);
+            self.legacy_fee_basis_points().set(&fee_basis_points);
+            self.legacy_fees_in_percent().clear();
+        }
+    }
+
+    /// 1 to 2: the single `acceptedPaymentTokenId` with its `minAmount` and fee
+    /// into an accepted token entry.
+    fn migrate_legacy_payment_token(&self) -> SCResult<()> {
+        if !self.legacy_payment_token_id().is_empty() {
+            let token_id = self.legacy_payment_token_id().get();
+            let min_amount = self.legacy_min_amount().get();
+            let fee_basis_points = self.legacy_fee_basis_points().get();
+            self.add_token_config(&token_id, &min_amount, &fee_basis_points)?;
+
+            self.legacy_payment_token_id().clear();
+            self.legacy_min_amount().clear();
+            self.legacy_fee_basis_points().clear();
+        }
+        Ok(())
+    }
+
+    fn add_token_config(
+        &self,
+        token_id: &TokenIdentifier,
+        min_amount: &BigUint,
+        fee_basis_points: &BigUint,
+    ) -> SCResult<()> {
+        self.require_valid_payment_token(token_id)?;
+        self.require_valid_min_amount(min_amount)?;
+        self.require_valid_fee_basis_points(fee_basis_points)?;
+
+        self.accepted_tokens().insert(token_id.clone());
+        self.min_amount(token_id).set(min_amount);
+        self.fee_basis_points(token_id).set(fee_basis_points);
+
+        Ok(())
+    }
+
+    fn require_not_paused(&self) -> SCResult<()> {
+        require!(!self.paused().get(), "Gateway is paused");
+        Ok(())
+    }
+
+    fn require_owner_or_pauser(&self) -> SCResult<()> {
+        let caller = self.blockchain().get_caller();
+        require!(
+            caller == self.blockchain().get_owner_address() || self.pausers().contains(&caller),
+            "Only the owner or a pauser can do this"
+        );
+        Ok(())
+    }
+
+    fn accept_token(
+        &self,
+        token_id: &TokenIdentifier,
+        min_amount: &BigUint,
+        fee_basis_points: &BigUint,
+    ) -> SCResult<()> {
+        require!(
+            !self.accepted_tokens().contains(token_id),
+            "Token is already accepted"
+        );
+        self.add_token_config(token_id, min_amount, fee_basis_points)?;
+        self.accepted_token_added_event(token_id, min_amount, fee_basis_points);
+        Ok(())
+    }
+
+    fn update_min_amount(&self, token_id: &TokenIdentifier, min_amount: &BigUint) -> SCResult<()> {
+        self.require_accepted_token(token_id)?;
+        self.require_valid_min_amount(min_amount)?;
+        self.min_amount(token_id).set(min_amount);
+        self.min_amount_changed_event(token_id, min_amount);
+        Ok(())
+    }
+
+    fn update_fee_basis_points(
+        &self,
+        token_id: &TokenIdentifier,
+        fee_basis_points: &BigUint,
+    ) -> SCResult<()> {
+        self.require_accepted_token(token_id)?;
+        self.require_valid_fee_basis_points(fee_basis_points)?;
+        self.fee_basis_points(token_id).set(fee_basis_points);
+        self.fee_basis_points_changed_event(token_id, fee_basis_points);
+        Ok(())
+    }
+
+    fn update_fees_addr(&self, fees_addr: &ManagedAddress) {
+        self.accepted_fees_addr_id().set(fees_addr);
+        self.fees_addr_changed_event(fees_addr);
+    }
+
+    fn update_rest_addr(&self, rest_addr: &ManagedAddress) {
+        self.accepted_rest_addr_id().set(rest_addr);
+        self.rest_addr_changed_event(rest_addr);
+    }
+
+    fn unaccept_token(&self, token_id: TokenIdentifier) -> SCResult<()> {
+        require!(
+            self.accepted_tokens().remove(&token_id),
+            "Token is not accepted"
+        );
+        self.min_amount(&token_id).clear();
+        self.fee_basis_points(&token_id).clear();
+        self.fee_tiers(&token_id).clear();
+        self.min_fee(&token_id).clear();
+        self.max_fee(&token_id).clear();
+        self.max_payment_amount(&token_id).clear();
+        self.daily_volume_limit(&token_id).clear();
+        self.fee_rounding(&token_id).clear();
+        self.fee_remainder(&token_id).clear();
+        self.accepted_token_removed_event(&token_id);
+        Ok(())
+    }
+
+    fn update_nft_flat_fee(
+        &self,
+        collection: TokenIdentifier,
+        fee_token: TokenIdentifier,
+        fee_amount: BigUint,
+    ) -> SCResult<()> {
+        require!(collection.is_valid_esdt_identifier(), "Invalid collection identifier");
+        require!(
+            fee_token.is_valid_esdt_identifier(),
+            "Fee token must be an ESDT, EGLD cannot be sent along an NFT"
+        );
+        require!(fee_amount > 0, "Fee amount must be greater than zero");
+        self.nft_fee_token(&collection).set(&fee_token);
+        self.nft_fee_amount(&collection).set(&fee_amount);
+        self.nft_flat_fee_changed_event(&collection, &fee_token, &fee_amount);
+        Ok(())
+    }
+
+    fn unaccept_nft_collection(&self, collection: TokenIdentifier) -> SCResult<()> {
+        require!(
+            !self.nft_fee_token(&collection).is_empty(),
+            "NFT collection is not accepted"
+        );
+        self.nft_fee_token(&collection).clear();
+        self.nft_fee_amount(&collection).clear();
+        self.nft_collection_removed_event(&collection);
+        Ok(())
+    }
+
+    fn update_fee_rounding(
+        &self,
+        token_id: TokenIdentifier,
+        fee_rounding: FeeRounding,
+    ) -> SCResult<()> {
+        self.require_accepted_token(&token_id)?;
+        self.fee_rounding(&token_id).set(&fee_rounding);
+        self.fee_rounding_changed_event(&token_id, &fee_rounding);
+        Ok(())
+    }
+
+    fn update_fee_tiers(
+        &self,
+        token_id: TokenIdentifier,
+        fee_tiers: Vec<FeeTier<Self::Api>>,
+    ) -> SCResult<()> {
+        self.require_accepted_token(&token_id)?;
+
+        self.fee_tiers(&token_id).clear();
+        let mut previous_threshold = BigUint::zero();
+        for tier in fee_tiers {
+            require!(
+                tier.threshold > previous_threshold,
+                "Tier thresholds must be strictly increasing"
+            );
+            self.require_valid_fee_basis_points(&tier.fee_basis_points)?;
+            previous_threshold = tier.threshold.clone();
+            self.fee_tiers(&token_id).push(&tier);
+        }
+        self.fee_tiers_changed_event(&token_id);
+        Ok(())
+    }
+
+    fn update_fee_bounds(
+        &self,
+        token_id: TokenIdentifier,
+        min_fee: BigUint,
+        max_fee: BigUint,
+    ) -> SCResult<()> {
+        self.require_accepted_token(&token_id)?;
+        require!(
+            max_fee == 0 || min_fee <= max_fee,
+            "Min fee cannot be greater than max fee"
+        );
+
+        if min_fee == 0 {
+            self.min_fee(&token_id).clear();
+        } else {
+            self.min_fee(&token_id).set(&min_fee);
+        }
+        if max_fee == 0 {
+            self.max_fee(&token_id).clear();
+        } else {
+            self.max_fee(&token_id).set(&max_fee);
+        }
+        self.fee_bounds_changed_event(&token_id, &min_fee, &max_fee);
+        Ok(())
+    }
+
+    fn add_referrer(&self, referrer: ManagedAddress) -> SCResult<()> {
+        require!(self.referrers().insert(referrer.clone()), "Referrer is already registered");
+        self.referrer_registered_event(&referrer);
+        Ok(())
+    }
+
+    fn remove_referrer(&self, referrer: ManagedAddress) -> SCResult<()> {
+        require!(self.referrers().remove(&referrer), "Referrer is not registered");
+        self.referrer_unregistered_event(&referrer);
+        Ok(())
+    }
+
+    fn update_referral_fee_share(&self, referral_fee_share: BigUint) -> SCResult<()> {
+        require!(
+            referral_fee_share <= FEE_DENOMINATOR,
+            "Referral fee share cannot exceed 10000"
+        );
+        self.referral_fee_share().set(&referral_fee_share);
+        self.referral_fee_share_changed_event(&referral_fee_share);
+        Ok(())
+    }
+
+    fn update_fee_exemption(
+        &self,
+        address: ManagedAddress,
+        fee_basis_points: BigUint,
+    ) -> SCResult<()> {
+        require!(
+            fee_basis_points <= FEE_DENOMINATOR,
+            "Fee basis points cannot exceed 10000"
+        );
+        self.fee_exempt_addresses().insert(address.clone());
+        self.exempted_fee_rate(&address).set(&fee_basis_points);
+        self.fee_exemption_set_event(&address, &fee_basis_points);
+        Ok(())
+    }
+
+    fn clear_fee_exemption(&self, address: ManagedAddress) -> SCResult<()> {
+        require!(
+            self.fee_exempt_addresses().remove(&address),
+            "Address is not exempted"
+        );
+        self.exempted_fee_rate(&address).clear();
+        self.fee_exemption_removed_event(&address);
+        Ok(())
+    }
+
+    fn add_merchant(
+        &self,
+        payout_address: ManagedAddress,
+        fee_basis_points: Option<BigUint>,
+    ) -> SCResult<u64> {
+        if let Some(fee_basis_points) = &fee_basis_points {
+            self.require_valid_fee_basis_points(fee_basis_points)?;
+        }
+
+        let merchant_id = self.last_merchant_id().get() + 1;
+        self.last_merchant_id().set(&merchant_id);
+        self.merchants(merchant_id).set(&Merchant {
+            payout_address,
+            fee_basis_points,
+            active: true,
+        });
+        self.merchant_registered_event(merchant_id);
+        Ok(merchant_id)
+    }
+
+    fn update_merchant_payout_address(
+        &self,
+        merchant_id: u64,
+        payout_address: ManagedAddress,
+    ) -> SCResult<()> {
+        self.require_merchant_exists(merchant_id)?;
+        self.merchants(merchant_id)
+            .update(|merchant| merchant.payout_address = payout_address);
+        self.merchant_updated_event(merchant_id);
+        Ok(())
+    }
+
+    fn update_merchant_fee_basis_points(
+        &self,
+        merchant_id: u64,
+        fee_basis_points: Option<BigUint>,
+    ) -> SCResult<()> {
+        self.require_merchant_exists(merchant_id)?;
+        if let Some(fee_basis_points) = &fee_basis_points {
+            self.require_valid_fee_basis_points(fee_basis_points)?;
+        }
+        self.merchants(merchant_id)
+            .update(|merchant| merchant.fee_basis_points = fee_basis_points);
+        self.merchant_updated_event(merchant_id);
+        Ok(())
+    }
+
+    fn update_merchant_settlement_token(
+        &self,
+        merchant_id: u64,
+        opt_token_id: Option<TokenIdentifier>,
+    ) -> SCResult<()> {
+        self.require_merchant_exists(merchant_id)?;
+        match opt_token_id {
+            Some(token_id) => {
+                require!(token_id.is_valid_esdt_identifier(), "Invalid token identifier");
+                self.merchant_settlement_token(merchant_id).set(&token_id);
+            },
+            None => self.merchant_settlement_token(merchant_id).clear(),
+        }
+        self.merchant_updated_event(merchant_id);
+        Ok(())
+    }
+
+    fn update_dex_pair(
+        &self,
+        token_in: TokenIdentifier,
+        token_out: TokenIdentifier,
+        pair_address: ManagedAddress,
+    ) -> SCResult<()> {
+        require!(
+            self.blockchain().is_smart_contract(&pair_address),
+            "Pair address must be a smart contract"
+        );
+        self.dex_pair(&token_in, &token_out).set(&pair_address);
+        self.dex_pair_changed_event(&token_in, &token_out, &pair_address);
+        Ok(())
+    }
+
+    fn clear_dex_pair(&self, token_in: TokenIdentifier, token_out: TokenIdentifier) -> SCResult<()> {
+        require!(!self.dex_pair(&token_in, &token_out).is_empty(), "Unknown DEX pair");
+        self.dex_pair(&token_in, &token_out).clear();
+        self.dex_pair_removed_event(&token_in, &token_out);
+        Ok(())
+    }
+
+    fn update_egld_wrapper(
+        &self,
+        wrapper_address: ManagedAddress,
+        wrapped_egld_token_id: TokenIdentifier,
+    ) -> SCResult<()> {
+        require!(
+            self.blockchain().is_smart_contract(&wrapper_address),
+            "Wrapper address must be a smart contract"
+        );
+        require!(
+            wrapped_egld_token_id.is_valid_esdt_identifier(),
+            "Invalid token identifier"
+        );
+        self.egld_wrapper_address().set(&wrapper_address);
+        self.wrapped_egld_token_id().set(&wrapped_egld_token_id);
+        self.egld_wrapper_changed_event(&wrapper_address, &wrapped_egld_token_id);
+        Ok(())
+    }
+
+    fn update_convert_fees(&self, convert_fees: bool) -> SCResult<()> {
+        self.convert_fees().set(&convert_fees);
+        self.convert_fees_changed_event(convert_fees);
+        Ok(())
+    }
+
+    fn update_payout_split(
+        &self,
+        remainder_addr: ManagedAddress,
+        shares: Vec<PayoutShare<Self::Api>>,
+    ) -> SCResult<()> {
+        require!(!shares.is_empty(), "Payout split cannot be empty");
+
+        let mut has_remainder_addr = false;
+        self.payout_shares().clear();
+        for share in shares {
+            require!(share.weight > 0, "Share weight must be greater than zero");
+            if share.address == remainder_addr {
+                has_remainder_addr = true;
+            }
+            self.payout_shares().push(&share);
+        }
+        require!(has_remainder_addr, "Remainder address must be one of the shares");
+        self.payout_remainder_addr().set(&remainder_addr);
+        self.payout_split_changed_event(&remainder_addr);
+        Ok(())
+    }
+
+    fn remove_payout_split(&self) -> SCResult<()> {
+        self.payout_shares().clear();
+        self.payout_remainder_addr().clear();
+        self.payout_split_changed_event(&self.accepted_rest_addr_id().get());
+        Ok(())
+    }
+
+    /// Applies a configuration change requested by the owner, as long as there is no board.
+    fn perform_owner_action(&self, action: BoardAction<Self::Api>) -> SCResult<()> {
+        self.require_board_inactive()?;
+        self.perform_board_action(action)
+    }
+
+    fn perform_board_action(&self, action: BoardAction<Self::Api>) -> SCResult<()> {
+        match action {
+            BoardAction::AddAcceptedToken(token_id, min_amount, fee_basis_points) => {
+                self.accept_token(&token_id, &min_amount, &fee_basis_points)?;
+            },
+            BoardAction::SetMinAmount(token_id, min_amount) => {
+                self.update_min_amount(&token_id, &min_amount)?;
+            },
+            BoardAction::SetFeeBasisPoints(token_id, fee_basis_points) => {
+                self.update_fee_basis_points(&token_id, &fee_basis_points)?;
+            },
+            BoardAction::RemoveAcceptedToken(token_id) => self.unaccept_token(token_id)?,
+            BoardAction::SetNftFlatFee(collection, fee_token, fee_amount) => {
+                self.update_nft_flat_fee(collection, fee_token, fee_amount)?;
+            },
+            BoardAction::RemoveNftCollection(collection) => {
+                self.unaccept_nft_collection(collection)?;
+            },
+            BoardAction::SetFeeRounding(token_id, fee_rounding) => {
+                self.update_fee_rounding(token_id, fee_rounding)?;
+            },
+            BoardAction::SetFeeTiers(token_id, fee_tiers) => {
+                self.update_fee_tiers(token_id, fee_tiers)?;
+            },
+            BoardAction::SetFeeBounds(token_id, min_fee, max_fee) => {
+                self.update_fee_bounds(token_id, min_fee, max_fee)?;
+            },
+            BoardAction::RegisterReferrer(referrer) => self.add_referrer(referrer)?,
+            BoardAction::UnregisterReferrer(referrer) => self.remove_referrer(referrer)?,
+            BoardAction::SetReferralFeeShare(referral_fee_share) => {
+                self.update_referral_fee_share(referral_fee_share)?;
+            },
+            BoardAction::SetFeeExemption(address, fee_basis_points) => {
+                self.update_fee_exemption(address, fee_basis_points)?;
+            },
+            BoardAction::RemoveFeeExemption(address) => self.clear_fee_exemption(address)?,
+            BoardAction::SetFeesAddr(fees_addr) => self.update_fees_addr(&fees_addr),
+            BoardAction::SetRestAddr(rest_addr) => self.update_rest_addr(&rest_addr),
+            BoardAction::SetPayoutSplit(remainder_addr, shares) => {
+                self.update_payout_split(remainder_addr, shares)?;
+            },
+            BoardAction::ClearPayoutSplit => self.remove_payout_split()?,
+            BoardAction::RegisterMerchant(payout_address, fee_basis_points) => {
+                self.add_merchant(payout_address, fee_basis_points)?;
+            },
+            BoardAction::SetMerchantPayoutAddress(merchant_id, payout_address) => {
+                self.update_merchant_payout_address(merchant_id, payout_address)?;
+            },
+            BoardAction::SetMerchantFeeBasisPoints(merchant_id, fee_basis_points) => {
+                self.update_merchant_fee_basis_points(merchant_id, fee_basis_points)?;
+            },
+            BoardAction::SetMerchantSettlementToken(merchant_id, opt_token_id) => {
+                self.update_merchant_settlement_token(merchant_id, opt_token_id)?;
+            },
+            BoardAction::SetDexPair(token_in, token_out, pair_address) => {
+                self.update_dex_pair(token_in, token_out, pair_address)?;
+            },
+            BoardAction::RemoveDexPair(token_in, token_out) => {
+                self.clear_dex_pair(token_in, token_out)?;
+            },
+            BoardAction::SetEgldWrapper(wrapper_address, wrapped_egld_token_id) => {
+                self.update_egld_wrapper(wrapper_address, wrapped_egld_token_id)?;
+            },
+            BoardAction::SetConvertFees(convert_fees) => self.update_convert_fees(convert_fees)?,
+            BoardAction::AddBoardMember(member) => {
+                require!(
+                    self.board_members().insert(member.clone()),
+                    "Address is already a board member"
+                );
+                self.board_member_added_event(&member);
+            },
+            BoardAction::RemoveBoardMember(member) => {
+                require!(
+                    self.board_members().remove(&member),
+                    "Address is not a board member"
+                );
+                self.require_valid_quorum(self.quorum().get())?;
+                self.board_member_removed_event(&member);
+            },
+            BoardAction::ChangeQuorum(quorum) => {
+                self.require_valid_quorum(quorum)?;
+                self.quorum().set(&quorum);
+                self.quorum_changed_event(quorum);
+            },
+        }
+        Ok(())
+    }
+
+    /// Signatures of `proposal_id` from addresses that are still board members.
+    fn proposal_signature_count(&self, proposal_id: u64) -> usize {
+        self.proposal_signers(proposal_id)
+            .iter()
+            .filter(|signer| self.board_members().contains(signer))
+            .count()
+    }
+
+    fn remove_proposal(&self, proposal_id: u64) {
+        self.proposals(proposal_id).clear();
+        self.proposal_signers(proposal_id).clear();
+        self.pending_proposals().remove(&proposal_id);
+    }
+
+    fn require_board_inactive(&self) -> SCResult<()> {
+        require!(
+            self.board_members().is_empty(),
+            "Configuration changes go through the board"
+        );
+        Ok(())
+    }
+
+    fn require_board_member(&self, address: &ManagedAddress) -> SCResult<()> {
+        require!(self.board_members().contains(address), "Only a board member can do this");
+        Ok(())
+    }
+
+    fn require_pending_proposal(&self, proposal_id: u64) -> SCResult<()> {
+        require!(
+            self.pending_proposals().contains(&proposal_id),
+            "Unknown proposal"
+        );
+        Ok(())
+    }
+
+    fn require_valid_quorum(&self, quorum: usize) -> SCResult<()> {
+        require!(
+            quorum > 0 && quorum <= self.board_members().len(),
+            "Quorum must be between 1 and the number of board members"
+        );
+        Ok(())
+    }
+
+    fn require_owner_or_compliance_officer(&self) -> SCResult<()> {
+        let caller = self.blockchain().get_caller();
+        require!(
+            caller == self.blockchain().get_owner_address()
+                || self.compliance_officers().contains(&caller),
+            "Only the owner or a compliance officer can do this"
+        );
+        Ok(())
+    }
+
+    fn require_allowed_payer(&self, payer: &ManagedAddress) -> SCResult<()> {
+        require!(!self.blocked_addresses().contains(payer), "Payer is blocked");
+        require!(
+            !self.allowlist_mode().get() || self.allowed_payers().contains(payer),
+            "Payer is not allowed"
+        );
+        Ok(())
+    }
+
+    fn require_merchant_exists(&self, merchant_id: u64) -> SCResult<()> {
+        require!(!self.merchants(merchant_id).is_empty(), "Merchant does not exist");
+        Ok(())
+    }
+
+    fn require_active_merchant(&self, merchant_id: u64) -> SCResult<()> {
+        self.require_merchant_exists(merchant_id)?;
+        require!(self.merchants(merchant_id).get().active, "Merchant is not active");
+        Ok(())
+    }
+
+    fn require_accepted_token(&self, token_id: &TokenIdentifier) -> SCResult<()> {
+        require!(self.accepted_tokens().contains(token_id), "Token is not accepted");
+        Ok(())
+    }
+
+    fn require_valid_min_amount(&self, min_amount: &BigUint) -> SCResult<()> {
+        require!(*min_amount >= 0, "Min amount must be greater than or equal to zero");
+        Ok(())
+    }
+
+    fn require_valid_fee_basis_points(&self, fee_basis_points: &BigUint) -> SCResult<()> {
+        require!(*fee_basis_points > 0, "Fee basis points must be greater than zero");
+        require!(
+            *fee_basis_points <= FEE_DENOMINATOR,
+            "Fee basis points cannot exceed 10000"
+        );
+        Ok(())
+    }
+
+    fn require_valid_payment_token(&self, token_id: &TokenIdentifier) -> SCResult<()> {
+        require!(token_id.is_valid_esdt_identifier() || token_id.is_egld(), "Invalid token identifier");
+        Ok(())
+    }
+
+    // proxies
+
+    #[proxy]
+    fn dex_pair_proxy(&self, to: ManagedAddress) -> dex_pair_proxy::Proxy<Self::Api>;
+
+    #[proxy]
+    fn egld_wrapper_proxy(&self, to: ManagedAddress) -> egld_wrapper_proxy::Proxy<Self::Api>;
+
+    // storage
+
+    #[storage_mapper("acceptedTokens")]
+    fn accepted_tokens(&self) -> SetMapper<TokenIdentifier>;
+
+    #[view(getAcceptedFeesAddr)]
+    #[storage_mapper("acceptedFeesAddrId")]
+    fn accepted_fees_addr_id(&self) -> SingleValueMapper<ManagedAddress>;
+
+    #[view(getAcceptedRestAddr)]
+    #[storage_mapper("acceptedRestAddrId")]
+    fn accepted_rest_addr_id(&self) -> SingleValueMapper<ManagedAddress>;
+
+    #[view(getMinAmount)]
+    #[storage_mapper("tokenMinAmount")]
+    fn min_amount(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[view(getFeeBasisPoints)]
+    #[storage_mapper("tokenFeeBasisPoints")]
+    fn fee_basis_points(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("feeTiers")]
+    fn fee_tiers(&self, token_id: &TokenIdentifier) -> VecMapper<FeeTier<Self::Api>>;
+
+    #[view(getMinFee)]
+    #[storage_mapper("minFee")]
+    fn min_fee(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[view(getMaxFee)]
+    #[storage_mapper("maxFee")]
+    fn max_fee(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[view(getMaxPaymentAmount)]
+    #[storage_mapper("maxPaymentAmount")]
+    fn max_payment_amount(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[view(getDailyVolumeLimit)]
+    #[storage_mapper("dailyVolumeLimit")]
+    fn daily_volume_limit(&self, token_id: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    /// Payments of `payer` in the current window and before, oldest first.
+    /// The first `payerVolumeExpiredCount` entries are out of the window and cleared.
+    #[storage_mapper("payerVolumeEntries")]
+    fn payer_volume_entries(
+        &self,
+        payer: &ManagedAddress,
+        token_id: &TokenIdentifier,
+    ) -> VecMapper<VolumeEntry<Self::Api>>;
+
+    #[storage_mapper("payerVolumeExpiredCount")]
+    fn payer_volume_expired_count(
+        &self,
+        payer: &ManagedAddress,
+        token_id: &TokenIdentifier,
+    ) -> SingleValueMapper<usize>;
+
+    /// Sum of the entries of `payer` not yet expired, as of its last payment.
+    #[storage_mapper("payerVolume")]
+    fn payer_volume(
+        &self,
+        payer: &ManagedAddress,
+        token_id: &TokenIdentifier,
+    ) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("nftFeeToken")]
+    fn nft_fee_token(&self, collection: &TokenIdentifier) -> SingleValueMapper<TokenIdentifier>;
+
+    #[storage_mapper("nftFeeAmount")]
+    fn nft_fee_amount(&self, collection: &TokenIdentifier) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("payoutShares")]
+    fn payout_shares(&self) -> VecMapper<PayoutShare<Self::Api>>;
+
+    #[view(getPayoutRemainderAddr)]
+    #[storage_mapper("payoutRemainderAddr")]
+    fn payout_remainder_addr(&self) -> SingleValueMapper<ManagedAddress>;
+
+    #[view(getLastMerchantId)]
+    #[storage_mapper("lastMerchantId")]
+    fn last_merchant_id(&self) -> SingleValueMapper<u64>;
+
+    #[view(getMerchant)]
+    #[storage_mapper("merchants")]
+    fn merchants(&self, merchant_id: u64) -> SingleValueMapper<Merchant<Self::Api>>;
+
+    #[view(getMerchantSettlementToken)]
+    #[storage_mapper("merchantSettlementToken")]
+    fn merchant_settlement_token(&self, merchant_id: u64) -> SingleValueMapper<TokenIdentifier>;
+
+    #[view(getDexPair)]
+    #[storage_mapper("dexPair")]
+    fn dex_pair(
+        &self,
+        token_in: &TokenIdentifier,
+        token_out: &TokenIdentifier,
+    ) -> SingleValueMapper<ManagedAddress>;
+
+    #[view(getEgldWrapperAddress)]
+    #[storage_mapper("egldWrapperAddress")]
+    fn egld_wrapper_address(&self) -> SingleValueMapper<ManagedAddress>;
+
+    #[view(getWrappedEgldTokenId)]
+    #[storage_mapper("wrappedEgldTokenId")]
+    fn wrapped_egld_token_id(&self) -> SingleValueMapper<TokenIdentifier>;
+
+    #[view(areFeesConverted)]
+    #[storage_mapper("convertFees")]
+    fn convert_fees(&self) -> SingleValueMapper<bool>;
+
+    #[view(getLastPaymentId)]
+    #[storage_mapper("lastPaymentId")]
+    fn last_payment_id(&self) -> SingleValueMapper<u64>;
+
+    #[view(getPayment)]
+    #[storage_mapper("payments")]
+    fn payments(&self, payment_id: u64) -> SingleValueMapper<PaymentRecord<Self::Api>>;
+
+    #[view(getPaymentIdByReference)]
+    #[storage_mapper("paymentIdByReference")]
+    fn payment_id_by_reference(&self, reference: &ManagedBuffer) -> SingleValueMapper<u64>;
+
+    #[view(areReferencesUnique)]
+    #[storage_mapper("uniqueReferences")]
+    fn unique_references(&self) -> SingleValueMapper<bool>;
+
+    #[storage_mapper("tokenStats")]
+    fn token_stats(&self, token_id: &TokenIdentifier) -> SingleValueMapper<PaymentStats<Self::Api>>;
+
+    #[storage_mapper("merchantStats")]
+    fn merchant_stats(
+        &self,
+        merchant_id: u64,
+        token_id: &TokenIdentifier,
+    ) -> SingleValueMapper<PaymentStats<Self::Api>>;
+
+    #[view(getReferrers)]
+    #[storage_mapper("referrers")]
+    fn referrers(&self) -> SetMapper<ManagedAddress>;
+
+    #[view(getReferralFeeShare)]
+    #[storage_mapper("referralFeeShare")]
+    fn referral_fee_share(&self) -> SingleValueMapper<BigUint>;
+
+    /// Total commissions ever earned by `referrer` in `token_id`, claimed or not.
+    #[view(getReferrerEarnings)]
+    #[storage_mapper("referrerEarnings")]
+    fn referrer_earnings(
+        &self,
+        referrer: &ManagedAddress,
+        token_id: &TokenIdentifier,
+    ) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("feeExemptAddresses")]
+    fn fee_exempt_addresses(&self) -> SetMapper<ManagedAddress>;
+
+    #[storage_mapper("exemptedFeeRate")]
+    fn exempted_fee_rate(&self, address: &ManagedAddress) -> SingleValueMapper<BigUint>;
+
+    #[view(getRefundWindow)]
+    #[storage_mapper("refundWindow")]
+    fn refund_window(&self) -> SingleValueMapper<u64>;
+
+    #[view(getRefundFeePolicy)]
+    #[storage_mapper("refundFeePolicy")]
+    fn refund_fee_policy(&self) -> SingleValueMapper<RefundFeePolicy>;
+
+    #[view(getEscrowTimeout)]
+    #[storage_mapper("escrowTimeout")]
+    fn escrow_timeout(&self) -> SingleValueMapper<u64>;
+
+    #[view(getLastEscrowId)]
+    #[storage_mapper("lastEscrowId")]
+    fn last_escrow_id(&self) -> SingleValueMapper<u64>;
+
+    #[view(getEscrow)]
+    #[storage_mapper("escrows")]
+    fn escrows(&self, escrow_id: u64) -> SingleValueMapper<Escrow<Self::Api>>;
+
+    /// Held escrow a reference is reserved for, while unique references are enabled.
+    #[storage_mapper("escrowIdByReference")]
+    fn escrow_id_by_reference(&self, reference: &ManagedBuffer) -> SingleValueMapper<u64>;
+
+    #[view(getLastInvoiceId)]
+    #[storage_mapper("lastInvoiceId")]
+    fn last_invoice_id(&self) -> SingleValueMapper<u64>;
+
+    #[view(getInvoice)]
+    #[storage_mapper("invoices")]
+    fn invoices(&self, invoice_id: u64) -> SingleValueMapper<Invoice<Self::Api>>;
+
+    #[view(getPayoutMode)]
+    #[storage_mapper("payoutMode")]
+    fn payout_mode(&self) -> SingleValueMapper<PayoutMode>;
+
+    #[storage_mapper("pendingTokens")]
+    fn pending_tokens(&self, address: &ManagedAddress) -> SetMapper<(TokenIdentifier, u64)>;
+
+    #[storage_mapper("pendingBalance")]
+    fn pending_balance(
+        &self,
+        address: &ManagedAddress,
+        token_id: &TokenIdentifier,
+        nonce: u64,
+    ) -> SingleValueMapper<BigUint>;
+
+    #[view(isPaused)]
+    #[storage_mapper("paused")]
+    fn paused(&self) -> SingleValueMapper<bool>;
+
+    #[view(getPausers)]
+    #[storage_mapper("pausers")]
+    fn pausers(&self) -> SetMapper<ManagedAddress>;
+
+    #[view(getComplianceOfficers)]
+    #[storage_mapper("complianceOfficers")]
+    fn compliance_officers(&self) -> SetMapper<ManagedAddress>;
+
+    #[view(getBlockedAddresses)]
+    #[storage_mapper("blockedAddresses")]
+    fn blocked_addresses(&self) -> SetMapper<ManagedAddress>;
+
+    #[view(getAllowedPayers)]
+    #[storage_mapper("allowedPayers")]
+    fn allowed_payers(&self) -> SetMapper<ManagedAddress>;
+
+    #[view(isAllowlistMode)]
+    #[storage_mapper("allowlistMode")]
+    fn allowlist_mode(&self) -> SingleValueMapper<bool>;
+
+    #[view(getBoardMembers)]
+    #[storage_mapper("boardMembers")]
+    fn board_members(&self) -> SetMapper<ManagedAddress>;
+
+    #[view(getQuorum)]
+    #[storage_mapper("quorum")]
+    fn quorum(&self) -> SingleValueMapper<usize>;
+
+    #[view(getLastProposalId)]
+    #[storage_mapper("lastProposalId")]
+    fn last_proposal_id(&self) -> SingleValueMapper<u64>;
+
+    #[view(getProposal)]
+    #[storage_mapper("proposals")]
+    fn proposals(&self, proposal_id: u64) -> SingleValueMapper<Proposal<Self::Api>>;
+
+    #[view(getProposalSigners)]
+    #[storage_mapper("proposalSigners")]
+    fn proposal_signers(&self, proposal_id: u64) -> SetMapper<ManagedAddress>;
+
+    #[storage_mapper("pendingProposals")]
+    fn pending_proposals(&self) -> SetMapper<u64>;
+
+    #[view(getSchemaVersion)]
+    #[storage_mapper("schemaVersion")]
+    fn schema_version(&self) -> SingleValueMapper<u32>;
+
+    // legacy storage, see `migrate_storage`
+
+    #[storage_mapper("acceptedPaymentTokenId")]
+    fn legacy_payment_token_id(&self) -> SingleValueMapper<TokenIdentifier>;
+
+    #[storage_mapper("minAmount")]
+    fn legacy_min_amount(&self) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("feeBasisPoints")]
+    fn legacy_fee_basis_points(&self) -> SingleValueMapper<BigUint>;
+
+    #[storage_mapper("feesInPercent")]
+    fn legacy_fees_in_percent(&self) -> SingleValueMapper<BigUint>;
+
+    // events
+
+    #[event("storageMigrated")]
+    fn storage_migrated_event(&self, #[indexed] from_version: u32, #[indexed] to_version: u32);
+
+    #[event("payment")]
+    fn payment_event(
+        &self,
+        #[indexed] payer: &ManagedAddress,
+        #[indexed] token_id: &TokenIdentifier,
+        #[indexed] nonce: u64,
+        payment: &PaymentEvent<Self::Api>,
+    );
+
+    #[event("refund")]
+    fn refund_event(
+        &self,
+        #[indexed] payer: &ManagedAddress,
+        #[indexed] payment_id: u64,
+        #[indexed] amount: &BigUint,
+        total_refunded: &BigUint,
+    );
+
+    #[event("escrowCreated")]
+    fn escrow_created_event(&self, #[indexed] payer: &ManagedAddress, #[indexed] escrow_id: u64);
+
+    #[event("escrowReleased")]
+    fn escrow_released_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        #[indexed] escrow_id: u64,
+        #[indexed] payment_id: u64,
+    );
+
+    #[event("escrowCancelled")]
+    fn escrow_cancelled_event(&self, #[indexed] caller: &ManagedAddress, #[indexed] escrow_id: u64);
+
+    #[event("invoiceCreated")]
+    fn invoice_created_event(&self, #[indexed] creator: &ManagedAddress, #[indexed] invoice_id: u64);
+
+    #[event("invoicePaid")]
+    fn invoice_paid_event(
+        &self,
+        #[indexed] payer: &ManagedAddress,
+        #[indexed] invoice_id: u64,
+        #[indexed] payment_id: u64,
+    );
+
+    #[event("invoiceCancelled")]
+    fn invoice_cancelled_event(&self, #[indexed] caller: &ManagedAddress, #[indexed] invoice_id: u64);
+
+    #[event("merchantRegistered")]
+    fn merchant_registered_event(&self, #[indexed] merchant_id: u64);
+
+    #[event("merchantUpdated")]
+    fn merchant_updated_event(&self, #[indexed] merchant_id: u64);
+
+    #[event("acceptedTokenAdded")]
+    fn accepted_token_added_event(
+        &self,
+        #[indexed] token_id: &TokenIdentifier,
+        #[indexed] min_amount: &BigUint,
+        #[indexed] fee_basis_points: &BigUint,
+    );
+
+    #[event("acceptedTokenRemoved")]
+    fn accepted_token_removed_event(&self, #[indexed] token_id: &TokenIdentifier);
+
+    #[event("feeTiersChanged")]
+    fn fee_tiers_changed_event(&self, #[indexed] token_id: &TokenIdentifier);
+
+    #[event("feeBoundsChanged")]
+    fn fee_bounds_changed_event(
+        &self,
+        #[indexed] token_id: &TokenIdentifier,
+        #[indexed] min_fee: &BigUint,
+        #[indexed] max_fee: &BigUint,
+    );
+
+    #[event("paymentLimitsChanged")]
+    fn payment_limits_changed_event(
+        &self,
+        #[indexed] token_id: &TokenIdentifier,
+        #[indexed] max_payment_amount: &BigUint,
+        #[indexed] daily_volume_limit: &BigUint,
+    );
+
+    #[event("nftFlatFeeChanged")]
+    fn nft_flat_fee_changed_event(
+        &self,
+        #[indexed] collection: &TokenIdentifier,
+        #[indexed] fee_token: &TokenIdentifier,
+        #[indexed] fee_amount: &BigUint,
+    );
+
+    #[event("nftCollectionRemoved")]
+    fn nft_collection_removed_event(&self, #[indexed] collection: &TokenIdentifier);
+
+    #[event("minAmountChanged")]
+    fn min_amount_changed_event(
+        &self,
+        #[indexed] token_id: &TokenIdentifier,
+        #[indexed] min_amount: &BigUint,
+    );
+
+    #[event("feeBasisPointsChanged")]
+    fn fee_basis_points_changed_event(
+        &self,
+        #[indexed] token_id: &TokenIdentifier,
+        #[indexed] fee_basis_points: &BigUint,
+    );
+
+    #[event("dexPairChanged")]
+    fn dex_pair_changed_event(
+        &self,
+        #[indexed] token_in: &TokenIdentifier,
+        #[indexed] token_out: &TokenIdentifier,
+        #[indexed] pair_address: &ManagedAddress,
+    );
+
+    #[event("dexPairRemoved")]
+    fn dex_pair_removed_event(
+        &self,
+        #[indexed] token_in: &TokenIdentifier,
+        #[indexed] token_out: &TokenIdentifier,
+    );
+
+    #[event("egldWrapperChanged")]
+    fn egld_wrapper_changed_event(
+        &self,
+        #[indexed] wrapper_address: &ManagedAddress,
+        #[indexed] wrapped_egld_token_id: &TokenIdentifier,
+    );
+
+    #[event("convertFeesChanged")]
+    fn convert_fees_changed_event(&self, #[indexed] convert_fees: bool);
+
+    #[event("referralCommission")]
+    fn referral_commission_event(
+        &self,
+        #[indexed] referrer: &ManagedAddress,
+        #[indexed] payment_id: u64,
+        #[indexed] token_id: &TokenIdentifier,
+        amount: &BigUint,
+    );
+
+    #[event("referrerRegistered")]
+    fn referrer_registered_event(&self, #[indexed] referrer: &ManagedAddress);
+
+    #[event("referrerUnregistered")]
+    fn referrer_unregistered_event(&self, #[indexed] referrer: &ManagedAddress);
+
+    #[event("referralFeeShareChanged")]
+    fn referral_fee_share_changed_event(&self, #[indexed] referral_fee_share: &BigUint);
+
+    #[event("feeExemptionSet")]
+    fn fee_exemption_set_event(
+        &self,
+        #[indexed] address: &ManagedAddress,
+        #[indexed] fee_basis_points: &BigUint,
+    );
+
+    #[event("feeExemptionRemoved")]
+    fn fee_exemption_removed_event(&self, #[indexed] address: &ManagedAddress);
+
+    #[event("refundWindowChanged")]
+    fn refund_window_changed_event(&self, #[indexed] refund_window: u64);
+
+    #[event("refundFeePolicyChanged")]
+    fn refund_fee_policy_changed_event(&self, #[indexed] refund_fee_policy: &RefundFeePolicy);
+
+    #[event("escrowTimeoutChanged")]
+    fn escrow_timeout_changed_event(&self, #[indexed] escrow_timeout: u64);
+
+    #[event("uniqueReferencesChanged")]
+    fn unique_references_changed_event(&self, #[indexed] unique_references: bool);
+
+    #[event("feesAddrChanged")]
+    fn fees_addr_changed_event(&self, #[indexed] fees_addr: &ManagedAddress);
+
+    #[event("payoutSplitChanged")]
+    fn payout_split_changed_event(&self, #[indexed] remainder_addr: &ManagedAddress);
+
+    #[event("restAddrChanged")]
+    fn rest_addr_changed_event(&self, #[indexed] rest_addr: &ManagedAddress);
+
+    #[event("pause")]
+    fn pause_event(&self, #[indexed] caller: &ManagedAddress);
+
+    #[event("unpause")]
+    fn unpause_event(&self, #[indexed] caller: &ManagedAddress);
+
+    #[event("pauserAdded")]
+    fn pauser_added_event(&self, #[indexed] address: &ManagedAddress);
+
+    #[event("pauserRemoved")]
+    fn pauser_removed_event(&self, #[indexed] address: &ManagedAddress);
+
+    #[event("boardMemberAdded")]
+    fn board_member_added_event(&self, #[indexed] member: &ManagedAddress);
+
+    #[event("boardMemberRemoved")]
+    fn board_member_removed_event(&self, #[indexed] member: &ManagedAddress);
+
+    #[event("quorumChanged")]
+    fn quorum_changed_event(&self, #[indexed] quorum: usize);
+
+    #[event("proposalCreated")]
+    fn proposal_created_event(
+        &self,
+        #[indexed] proposer: &ManagedAddress,
+        #[indexed] proposal_id: u64,
+    );
+
+    #[event("proposalSigned")]
+    fn proposal_signed_event(
+        &self,
+        #[indexed] signer: &ManagedAddress,
+        #[indexed] proposal_id: u64,
+    );
+
+    #[event("proposalUnsigned")]
+    fn proposal_unsigned_event(
+        &self,
+        #[indexed] signer: &ManagedAddress,
+        #[indexed] proposal_id: u64,
+    );
+
+    #[event("proposalPerformed")]
+    fn proposal_performed_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        #[indexed] proposal_id: u64,
+    );
+
+    #[event("proposalDiscarded")]
+    fn proposal_discarded_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        #[indexed] proposal_id: u64,
+    );
+
+    #[event("complianceOfficerAdded")]
+    fn compliance_officer_added_event(&self, #[indexed] address: &ManagedAddress);
+
+    #[event("complianceOfficerRemoved")]
+    fn compliance_officer_removed_event(&self, #[indexed] address: &ManagedAddress);
+
+    #[event("addressBlocked")]
+    fn address_blocked_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        #[indexed] address: &ManagedAddress,
+    );
+
+    #[event("addressUnblocked")]
+    fn address_unblocked_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        #[indexed] address: &ManagedAddress,
+    );
+
+    #[event("payerAllowed")]
+    fn payer_allowed_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        #[indexed] address: &ManagedAddress,
+    );
+
+    #[event("payerDisallowed")]
+    fn payer_disallowed_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        #[indexed] address: &ManagedAddress,
+    );
+
+    #[event("allowlistModeChanged")]
+    fn allowlist_mode_changed_event(
+        &self,
+        #[indexed] caller: &ManagedAddress,
+        #[indexed] allowlist_mode: bool,
+    );
+
+    #[event("payoutModeChanged")]
+    fn payout_mode_changed_event(&self, #[indexed] payout_mode: &PayoutMode);
+
+    #[event("claim")]
+    fn claim_event(
+        &self,
+        #[indexed] address: &ManagedAddress,
+        #[indexed] token_id: &TokenIdentifier,
+        #[indexed] nonce: u64,
+        amount: &BigUint,
+    );
 }
\ No newline at end of file
@@ -0,0 +1,3 @@
+fn main() {
+    elrond_wasm_debug::meta::perform::<gtwfees1::AbiProvider>();
+}
\ No newline at end of file
@@ -0,0 +1,3 @@
+fn main() {
+    elrond_wasm_debug::meta::perform::<mock_dex_pair::AbiProvider>();
+}
\ No newline at end of file
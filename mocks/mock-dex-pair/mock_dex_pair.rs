@@ -0,0 +1,50 @@
+#![no_std]
+
+elrond_wasm::imports!();
+
+/// A DEX pair stand-in for the gateway scenarios: swaps any token it receives into `token_out`
+/// at a fixed rate, paying `amount_in * rate_numerator / rate_denominator` from its own balance.
+/// `amount_out_min` is ignored, so that the gateway's own slippage check can be exercised.
+#[elrond_wasm::contract]
+pub trait MockDexPair {
+    #[init]
+    fn init(&self, rate_numerator: BigUint, rate_denominator: BigUint) -> SCResult<()> {
+        require!(rate_denominator > 0, "Rate denominator must be greater than zero");
+        self.rate_numerator().set(&rate_numerator);
+        self.rate_denominator().set(&rate_denominator);
+
+        Ok(())
+    }
+
+    #[payable("*")]
+    #[endpoint(swapTokensFixedInput)]
+    fn swap_tokens_fixed_input(
+        &self,
+        #[payment_amount] amount_in: BigUint,
+        token_out: TokenIdentifier,
+        _amount_out_min: BigUint,
+    ) -> SCResult<()> {
+        let amount_out =
+            amount_in * self.rate_numerator().get() / self.rate_denominator().get();
+        require!(amount_out > 0, "Swap output is zero");
+        self.send().direct(
+            &self.blockchain().get_caller(),
+            &token_out,
+            0,
+            &amount_out,
+            &[],
+        );
+
+        Ok(())
+    }
+
+    // storage
+
+    #[view(getRateNumerator)]
+    #[storage_mapper("rateNumerator")]
+    fn rate_numerator(&self) -> SingleValueMapper<BigUint>;
+
+    #[view(getRateDenominator)]
+    #[storage_mapper("rateDenominator")]
+    fn rate_denominator(&self) -> SingleValueMapper<BigUint>;
+}
\ No newline at end of file
@@ -0,0 +1,16 @@
+////////////////////////////////////////////////////
+////////////////// AUTO-GENERATED //////////////////
+////////////////////////////////////////////////////
+
+#![no_std]
+
+elrond_wasm_node::wasm_endpoints! {
+    mock_dex_pair
+    (
+        getRateDenominator
+        getRateNumerator
+        swapTokensFixedInput
+    )
+}
+
+elrond_wasm_node::wasm_empty_callback! {}
@@ -0,0 +1,34 @@
+#![no_std]
+
+elrond_wasm::imports!();
+
+/// An EGLD wrapper stand-in for the gateway scenarios: sends back the EGLD it receives
+/// as the same amount of `wrapped_egld_token_id`, taken from its own balance.
+#[elrond_wasm::contract]
+pub trait MockEgldWrapper {
+    #[init]
+    fn init(&self, wrapped_egld_token_id: TokenIdentifier) {
+        self.wrapped_egld_token_id().set(&wrapped_egld_token_id);
+    }
+
+    #[payable("EGLD")]
+    #[endpoint(wrapEgld)]
+    fn wrap_egld(&self, #[payment_amount] amount: BigUint) -> SCResult<()> {
+        require!(amount > 0, "Payment must be greater than zero");
+        self.send().direct(
+            &self.blockchain().get_caller(),
+            &self.wrapped_egld_token_id().get(),
+            0,
+            &amount,
+            &[],
+        );
+
+        Ok(())
+    }
+
+    // storage
+
+    #[view(getWrappedEgldTokenId)]
+    #[storage_mapper("wrappedEgldTokenId")]
+    fn wrapped_egld_token_id(&self) -> SingleValueMapper<TokenIdentifier>;
+}
\ No newline at end of file
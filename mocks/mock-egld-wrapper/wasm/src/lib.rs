@@ -0,0 +1,15 @@
+////////////////////////////////////////////////////
+////////////////// AUTO-GENERATED //////////////////
+////////////////////////////////////////////////////
+
+#![no_std]
+
+elrond_wasm_node::wasm_endpoints! {
+    mock_egld_wrapper
+    (
+        getWrappedEgldTokenId
+        wrapEgld
+    )
+}
+
+elrond_wasm_node::wasm_empty_callback! {}
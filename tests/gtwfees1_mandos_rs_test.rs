@@ -0,0 +1,77 @@
+use elrond_wasm_debug::*;
+
+fn world() -> BlockchainMock {
+    let mut blockchain = BlockchainMock::new();
+    blockchain.set_current_dir_from_workspace("");
+
+    blockchain.register_contract_builder("file:output/gtwfees1.wasm", gtwfees1::ContractBuilder);
+    blockchain.register_contract_builder(
+        "file:mocks/mock-dex-pair/output/mock-dex-pair.wasm",
+        mock_dex_pair::ContractBuilder,
+    );
+    blockchain.register_contract_builder(
+        "file:mocks/mock-egld-wrapper/output/mock-egld-wrapper.wasm",
+        mock_egld_wrapper::ContractBuilder,
+    );
+    blockchain
+}
+
+#[test]
+fn convert_swap_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/convert_swap.scen.json", world());
+}
+
+#[test]
+fn convert_min_out_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/convert_min_out.scen.json", world());
+}
+
+#[test]
+fn convert_egld_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/convert_egld.scen.json", world());
+}
+
+#[test]
+fn convert_fees_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/convert_fees.scen.json", world());
+}
+
+#[test]
+fn fee_tiers_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/fee_tiers.scen.json", world());
+}
+
+#[test]
+fn fee_bounds_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/fee_bounds.scen.json", world());
+}
+
+#[test]
+fn fee_rounding_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/fee_rounding.scen.json", world());
+}
+
+#[test]
+fn convert_refund_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/convert_refund.scen.json", world());
+}
+
+#[test]
+fn board_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/board.scen.json", world());
+}
+
+#[test]
+fn payment_limits_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/payment_limits.scen.json", world());
+}
+
+#[test]
+fn escrow_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/escrow.scen.json", world());
+}
+
+#[test]
+fn pause_rs() {
+    elrond_wasm_debug::mandos_rs("mandos/pause.scen.json", world());
+}
\ No newline at end of file
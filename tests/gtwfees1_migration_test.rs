@@ -0,0 +1,172 @@
+use elrond_wasm::types::{Address, OptionalArg, SCResult, TokenIdentifier};
+use elrond_wasm_debug::{
+    managed_address, managed_biguint, managed_token_id, rust_biguint, testing_framework::*,
+    tx_mock::TxResult, DebugApi,
+};
+use gtwfees1::{GtwFees1, SCHEMA_VERSION};
+
+const WASM_PATH: &str = "output/gtwfees1.wasm";
+const TOKEN_ID: &[u8] = b"TOK-123456";
+
+type GatewayObj = gtwfees1::ContractObj<DebugApi>;
+
+struct GatewaySetup {
+    blockchain: BlockchainStateWrapper,
+    owner: Address,
+    fees: Address,
+    rest: Address,
+    gateway: ContractObjWrapper<GatewayObj, fn() -> GatewayObj>,
+}
+
+impl GatewaySetup {
+    /// A gateway account whose `init` has not run yet, as before a deploy.
+    fn new() -> Self {
+        let rust_zero = rust_biguint!(0);
+        let mut blockchain = BlockchainStateWrapper::new();
+        let owner = blockchain.create_user_account(&rust_zero);
+        let fees = blockchain.create_user_account(&rust_zero);
+        let rest = blockchain.create_user_account(&rust_zero);
+        let gateway = blockchain.create_sc_account(
+            &rust_zero,
+            Some(&owner),
+            gtwfees1::contract_obj as fn() -> GatewayObj,
+            WASM_PATH,
+        );
+        GatewaySetup {
+            blockchain,
+            owner,
+            fees,
+            rest,
+            gateway,
+        }
+    }
+
+    /// Writes storage as an older version of the contract left it.
+    fn seed_storage(&mut self, seed: impl FnOnce(GatewayObj)) {
+        self.blockchain
+            .execute_tx(&self.owner, &self.gateway, &rust_biguint!(0), |sc| {
+                seed(sc);
+                StateChange::Commit
+            })
+            .assert_ok();
+    }
+
+    /// Runs `init` as a deploy or an upgrade does: 0 min amount and 1% fee for EGLD.
+    fn init(&mut self) -> TxResult {
+        let fees = self.fees.clone();
+        let rest = self.rest.clone();
+        self.blockchain
+            .execute_tx(&self.owner, &self.gateway, &rust_biguint!(0), |sc| {
+                let result = sc.init(
+                    managed_biguint!(0),
+                    managed_biguint!(100),
+                    managed_address!(&fees),
+                    managed_address!(&rest),
+                    OptionalArg::Some(TokenIdentifier::egld()),
+                );
+                assert_eq!(result, SCResult::Ok(()));
+                StateChange::Commit
+            })
+    }
+
+    fn check(&mut self, check: impl FnOnce(GatewayObj)) {
+        self.blockchain.execute_query(&self.gateway, check).assert_ok();
+    }
+}
+
+#[test]
+fn deploy_sets_schema_version() {
+    let mut setup = GatewaySetup::new();
+
+    setup.init().assert_ok();
+    setup.check(|sc| {
+        assert_eq!(sc.schema_version().get(), SCHEMA_VERSION);
+        assert!(sc.accepted_tokens().contains(&TokenIdentifier::egld()));
+        assert_eq!(sc.fee_basis_points(&TokenIdentifier::egld()).get(), managed_biguint!(100));
+    });
+}
+
+#[test]
+fn upgrade_migrates_fees_in_percent() {
+    let mut setup = GatewaySetup::new();
+    let fees = setup.fees.clone();
+    setup.seed_storage(|sc| {
+        sc.accepted_fees_addr_id().set(&managed_address!(&fees));
+        sc.legacy_payment_token_id().set(&managed_token_id!(TOKEN_ID));
+        sc.legacy_min_amount().set(&managed_biguint!(10));
+        sc.legacy_fees_in_percent().set(&managed_biguint!(2));
+    });
+
+    setup.init().assert_ok();
+    setup.check(|sc| {
+        let token_id = managed_token_id!(TOKEN_ID);
+        assert_eq!(sc.schema_version().get(), SCHEMA_VERSION);
+        assert!(sc.accepted_tokens().contains(&token_id));
+        assert_eq!(sc.min_amount(&token_id).get(), managed_biguint!(10));
+        assert_eq!(sc.fee_basis_points(&token_id).get(), managed_biguint!(200));
+        assert!(sc.legacy_fees_in_percent().is_empty());
+        assert!(sc.legacy_payment_token_id().is_empty());
+        assert!(sc.legacy_min_amount().is_empty());
+        assert!(sc.legacy_fee_basis_points().is_empty());
+    });
+}
+
+#[test]
+fn upgrade_migrates_legacy_payment_token() {
+    let mut setup = GatewaySetup::new();
+    let fees = setup.fees.clone();
+    setup.seed_storage(|sc| {
+        sc.accepted_fees_addr_id().set(&managed_address!(&fees));
+        sc.legacy_payment_token_id().set(&managed_token_id!(TOKEN_ID));
+        sc.legacy_min_amount().set(&managed_biguint!(10));
+        sc.legacy_fee_basis_points().set(&managed_biguint!(75));
+    });
+
+    setup.init().assert_ok();
+    setup.check(|sc| {
+        let token_id = managed_token_id!(TOKEN_ID);
+        assert_eq!(sc.schema_version().get(), SCHEMA_VERSION);
+        assert_eq!(sc.min_amount(&token_id).get(), managed_biguint!(10));
+        assert_eq!(sc.fee_basis_points(&token_id).get(), managed_biguint!(75));
+        assert!(sc.legacy_payment_token_id().is_empty());
+        assert!(sc.legacy_fee_basis_points().is_empty());
+    });
+}
+
+#[test]
+fn upgrade_ignores_arguments() {
+    let mut setup = GatewaySetup::new();
+    let owner = setup.owner.clone();
+    setup.seed_storage(|sc| {
+        let token_id = managed_token_id!(TOKEN_ID);
+        sc.schema_version().set(&SCHEMA_VERSION);
+        sc.accepted_fees_addr_id().set(&managed_address!(&owner));
+        sc.accepted_rest_addr_id().set(&managed_address!(&owner));
+        sc.accepted_tokens().insert(token_id.clone());
+        sc.min_amount(&token_id).set(&managed_biguint!(5));
+        sc.fee_basis_points(&token_id).set(&managed_biguint!(50));
+    });
+
+    setup.init().assert_ok();
+    setup.check(|sc| {
+        let token_id = managed_token_id!(TOKEN_ID);
+        assert_eq!(sc.accepted_fees_addr_id().get(), managed_address!(&owner));
+        assert_eq!(sc.accepted_rest_addr_id().get(), managed_address!(&owner));
+        assert!(!sc.accepted_tokens().contains(&TokenIdentifier::egld()));
+        assert_eq!(sc.min_amount(&token_id).get(), managed_biguint!(5));
+        assert_eq!(sc.fee_basis_points(&token_id).get(), managed_biguint!(50));
+    });
+}
+
+#[test]
+fn upgrade_cannot_downgrade_schema() {
+    let mut setup = GatewaySetup::new();
+    let fees = setup.fees.clone();
+    setup.seed_storage(|sc| {
+        sc.schema_version().set(&(SCHEMA_VERSION + 1));
+        sc.accepted_fees_addr_id().set(&managed_address!(&fees));
+    });
+
+    setup.init().assert_user_error("Cannot downgrade the storage schema");
+    setup.check(|sc| assert_eq!(sc.schema_version().get(), SCHEMA_VERSION + 1));
+}
\ No newline at end of file
@@ -0,0 +1,137 @@
+////////////////////////////////////////////////////
+////////////////// AUTO-GENERATED //////////////////
+////////////////////////////////////////////////////
+
+#![no_std]
+
+elrond_wasm_node::wasm_endpoints! {
+    gtwfees1
+    (
+        addAcceptedToken
+        addComplianceOfficer
+        addPauser
+        allowPayer
+        areFeesConverted
+        areReferencesUnique
+        blockAddress
+        cancelEscrow
+        cancelInvoice
+        claim
+        clearPayoutSplit
+        createEscrow
+        createInvoice
+        disallowPayer
+        discardProposal
+        getAcceptedFeesAddr
+        getAcceptedRestAddr
+        getAcceptedTokens
+        getAllowedPayers
+        getBlockedAddresses
+        getBoardMembers
+        getComplianceOfficers
+        getDailyVolumeLimit
+        getDexPair
+        getEffectiveFeeRate
+        getEgldWrapperAddress
+        getEscrow
+        getEscrowTimeout
+        getFeeBasisPoints
+        getFeeExemptions
+        getFeeRemainder
+        getFeeRounding
+        getFeeSchedule
+        getInvoice
+        getInvoiceStatus
+        getLastEscrowId
+        getLastInvoiceId
+        getLastMerchantId
+        getLastPaymentId
+        getLastProposalId
+        getMaxFee
+        getMaxPaymentAmount
+        getMerchant
+        getMerchantSettlementToken
+        getMerchantStats
+        getMinAmount
+        getMinFee
+        getNftFlatFee
+        getPausers
+        getPayment
+        getPaymentIdByReference
+        getPayoutMode
+        getPayoutRemainderAddr
+        getPayoutSplit
+        getPendingBalances
+        getPendingProposals
+        getProposal
+        getProposalSigners
+        getQuorum
+        getReferralFeeShare
+        getReferrerEarnings
+        getReferrers
+        getRefundFeePolicy
+        getRefundWindow
+        getRemainingAllowance
+        getSchemaVersion
+        getTokenStats
+        getWrappedEgldTokenId
+        isAllowlistMode
+        isPaused
+        isReferencePaid
+        pause
+        payInvoice
+        payMerchant
+        payMerchantConverted
+        payMerchantReferred
+        performProposal
+        propose
+        reclaimEscrow
+        refundPayment
+        registerMerchant
+        registerReferrer
+        releaseEscrow
+        removeAcceptedToken
+        removeComplianceOfficer
+        removeDexPair
+        removeFeeExemption
+        removeNftCollection
+        removePauser
+        sendMultiToken
+        sendNft
+        sendToken
+        sendTokenReferred
+        setAllowlistMode
+        setConvertFees
+        setDexPair
+        setEgldWrapper
+        setEscrowTimeout
+        setFeeBasisPoints
+        setFeeBounds
+        setFeeExemption
+        setFeeRounding
+        setFeeTiers
+        setFeesAddr
+        setMerchantActive
+        setMerchantFeeBasisPoints
+        setMerchantPayoutAddress
+        setMerchantSettlementToken
+        setMinAmount
+        setNftFlatFee
+        setPaymentLimits
+        setPayoutMode
+        setPayoutSplit
+        setReferralFeeShare
+        setRefundFeePolicy
+        setRefundWindow
+        setRestAddr
+        setUniqueReferences
+        setupBoard
+        signProposal
+        unblockAddress
+        unpause
+        unregisterReferrer
+        unsignProposal
+    )
+}
+
+elrond_wasm_node::wasm_empty_callback! {}